@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id VARCHAR PRIMARY KEY,
+    session_id VARCHAR,
+    message VARCHAR,
+    response VARCHAR,
+    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS idx_session ON messages (session_id);
+
+CREATE TABLE IF NOT EXISTS chat_sessions (
+    session_id VARCHAR PRIMARY KEY,
+    title VARCHAR,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,35 @@
+-- Split each exchange row into a user message and an assistant reply
+CREATE TEMP TABLE legacy_messages AS SELECT * FROM messages;
+DROP INDEX IF EXISTS idx_session;
+DROP TABLE messages;
+
+CREATE TABLE messages (
+    id VARCHAR PRIMARY KEY,
+    session_id VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    parent_id VARCHAR,
+    model VARCHAR,
+    prompt_tokens BIGINT,
+    completion_tokens BIGINT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_messages_session ON messages (session_id);
+CREATE INDEX idx_messages_parent ON messages (parent_id);
+
+-- Rows written without a session are gathered into one imported session
+INSERT INTO chat_sessions (session_id, title)
+    SELECT 'imported-legacy', 'Imported messages'
+    WHERE EXISTS (SELECT 1 FROM legacy_messages WHERE session_id IS NULL)
+    AND NOT EXISTS (SELECT 1 FROM chat_sessions WHERE session_id = 'imported-legacy');
+
+INSERT INTO messages (id, session_id, role, content, parent_id, created_at)
+    SELECT id, COALESCE(session_id, 'imported-legacy'), 'user', COALESCE(message, ''), NULL, timestamp
+    FROM legacy_messages ORDER BY timestamp;
+INSERT INTO messages (id, session_id, role, content, parent_id, created_at)
+    SELECT CAST(uuid() AS VARCHAR), COALESCE(session_id, 'imported-legacy'), 'assistant', response, id, timestamp
+    FROM legacy_messages
+    WHERE response IS NOT NULL AND response <> ''
+    ORDER BY timestamp;
+
+DROP TABLE legacy_messages;
@@ -0,0 +1,14 @@
+ALTER TABLE chat_sessions ADD COLUMN updated_at TIMESTAMP;
+ALTER TABLE chat_sessions ADD COLUMN pinned BOOLEAN DEFAULT false;
+ALTER TABLE chat_sessions ADD COLUMN archived BOOLEAN DEFAULT false;
+
+-- Sessions used to be registered only once a title was generated
+INSERT INTO chat_sessions (session_id, created_at)
+    SELECT session_id, MIN(created_at) FROM messages
+    WHERE session_id NOT IN (SELECT session_id FROM chat_sessions)
+    GROUP BY session_id;
+
+UPDATE chat_sessions SET updated_at = COALESCE(
+    (SELECT MAX(m.created_at) FROM messages m WHERE m.session_id = chat_sessions.session_id),
+    created_at
+);
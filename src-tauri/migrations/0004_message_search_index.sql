@@ -0,0 +1,17 @@
+-- Term frequencies per message, used to rank full-text search with BM25
+CREATE TABLE message_terms (
+    message_id VARCHAR NOT NULL,
+    term VARCHAR NOT NULL,
+    tf INTEGER NOT NULL
+);
+CREATE INDEX idx_message_terms_term ON message_terms (term);
+CREATE INDEX idx_message_terms_message ON message_terms (message_id);
+
+INSERT INTO message_terms (message_id, term, tf)
+    SELECT id, term, COUNT(*)
+    FROM (
+        SELECT id, unnest(regexp_split_to_array(lower(content), '[^\p{L}\p{N}_]+')) AS term
+        FROM messages
+    )
+    WHERE term <> ''
+    GROUP BY id, term;
@@ -0,0 +1,14 @@
+-- The message the session's visible conversation ends at
+ALTER TABLE chat_sessions ADD COLUMN active_leaf_id VARCHAR;
+
+-- User messages used to start from nothing; chain each one onto the message
+-- before it so every session forms a single branch
+DROP INDEX IF EXISTS idx_messages_parent;
+UPDATE messages SET parent_id = (
+    SELECT p.id FROM messages p
+    WHERE p.session_id = messages.session_id AND p.created_at < messages.created_at
+    ORDER BY p.created_at DESC, p.role = 'assistant' DESC
+    LIMIT 1
+)
+WHERE role = 'user' AND parent_id IS NULL;
+CREATE INDEX idx_messages_parent ON messages (parent_id);
@@ -0,0 +1,4 @@
+-- Rolling summary of the turns that no longer fit in the model's context,
+-- covering the active branch up to and including message summary_through
+ALTER TABLE chat_sessions ADD COLUMN summary VARCHAR;
+ALTER TABLE chat_sessions ADD COLUMN summary_through VARCHAR;
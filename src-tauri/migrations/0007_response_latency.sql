@@ -0,0 +1,2 @@
+-- Milliseconds from sending the prompt to the last token of the reply
+ALTER TABLE messages ADD COLUMN latency_ms BIGINT;
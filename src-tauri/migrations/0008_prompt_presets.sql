@@ -0,0 +1,16 @@
+CREATE TABLE prompt_presets (
+    id VARCHAR PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The preset whose system prompt is sent with every turn of the session
+ALTER TABLE chat_sessions ADD COLUMN preset_id VARCHAR;
+
+INSERT INTO prompt_presets (id, name, content) VALUES
+    (CAST(uuid() AS VARCHAR), 'Rust reviewer',
+     'You are an experienced Rust reviewer. Point out bugs, unidiomatic code, needless clones and unsafe error handling, and suggest concrete fixes. The project lives in {{project_dir}}; the open file is {{open_file}}.'),
+    (CAST(uuid() AS VARCHAR), 'SQL helper',
+     'You help write and optimise SQL. Explain query plans briefly, prefer standard SQL, and mention dialect-specific behaviour when it matters. Current language: {{language}}.');
@@ -0,0 +1,119 @@
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// One asciicast v2 event: output ("o") or a terminal resize ("r").
+#[derive(Clone, Debug)]
+pub struct CastEvent {
+    pub time: f64,
+    pub kind: &'static str,
+    pub data: String,
+}
+
+impl CastEvent {
+    pub fn output(time: f64, data: &str) -> Self {
+        CastEvent {
+            time,
+            kind: "o",
+            data: data.to_string(),
+        }
+    }
+
+    pub fn resize(time: f64, cols: u16, rows: u16) -> Self {
+        CastEvent {
+            time,
+            kind: "r",
+            data: format!("{}x{}", cols, rows),
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        json!([self.time, self.kind, self.data]).to_string()
+    }
+}
+
+pub fn header(width: u16, height: u16, timestamp: i64) -> String {
+    json!({
+        "version": 2,
+        "width": width,
+        "height": height,
+        "timestamp": timestamp,
+        "env": { "TERM": "xterm-256color" },
+    })
+    .to_string()
+}
+
+/// Streams a terminal session to an asciicast v2 file as it happens.
+pub struct Recorder {
+    started: Instant,
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: PathBuf, width: u16, height: u16) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create recordings dir: {}", e))?;
+        }
+        let file = File::create(&path).map_err(|e| format!("Failed to create recording: {}", e))?;
+        let mut recorder = Recorder {
+            started: Instant::now(),
+            file: BufWriter::new(file),
+        };
+        recorder.write_line(&header(width, height, chrono::Utc::now().timestamp()))?;
+        Ok(recorder)
+    }
+
+    pub fn output(&mut self, data: &str) -> Result<(), String> {
+        let event = CastEvent::output(self.started.elapsed().as_secs_f64(), data);
+        self.write_line(&event.to_line())
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<(), String> {
+        let event = CastEvent::resize(self.started.elapsed().as_secs_f64(), cols, rows);
+        self.write_line(&event.to_line())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.file, "{}", line)
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Failed to write recording: {}", e))
+    }
+}
+
+/// Reads the events of an asciicast v2 file, skipping event types we don't
+/// replay.
+pub fn read_events(path: &Path) -> Result<Vec<CastEvent>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open recording: {}", e))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header: Value = lines
+        .next()
+        .ok_or_else(|| "Recording is empty".to_string())?
+        .map_err(|e| format!("Failed to read recording: {}", e))
+        .and_then(|line| {
+            serde_json::from_str(&line).map_err(|e| format!("Invalid recording header: {}", e))
+        })?;
+    if header["version"] != 2 {
+        return Err("Only asciicast v2 recordings are supported".to_string());
+    }
+
+    let mut events = Vec::new();
+    for line in lines {
+        let line = line.map_err(|e| format!("Failed to read recording: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (time, kind, data): (f64, String, String) =
+            serde_json::from_str(&line).map_err(|e| format!("Invalid recording event: {}", e))?;
+        let kind = match kind.as_str() {
+            "o" => "o",
+            "r" => "r",
+            _ => continue,
+        };
+        events.push(CastEvent { time, kind, data });
+    }
+    Ok(events)
+}
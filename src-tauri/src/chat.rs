@@ -1,6 +1,9 @@
-use crate::db::Database;
+use crate::db::{
+    BranchMessage, ChatSession, ChatStats, Database, NewMessage, ResponseMeta, SessionFlag,
+    UNTITLED_SESSION_TITLE,
+};
 use serde::{Deserialize, Serialize};
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FrontendMessage {
@@ -8,12 +11,127 @@ pub struct FrontendMessage {
     pub content: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ChatSession {
-    pub id: String,
+// Characters of context kept around the first match in a search snippet
+const SNIPPET_CONTEXT: usize = 60;
+const SNIPPET_LENGTH: usize = 200;
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+
+const MAX_TITLE_CHARS: usize = 50;
+const HEURISTIC_TITLE_WORDS: usize = 6;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionTitleUpdate {
+    pub session_id: String,
     pub title: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DateRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatSearchHit {
+    pub message_id: String,
+    pub session_id: String,
+    pub session_title: String,
+    pub role: String,
+    pub snippet: String,
+    /// Character ranges within `snippet` that matched the query
+    pub highlights: Vec<(usize, usize)>,
+    pub created_at: String,
+    pub score: f64,
+}
+
+fn search_terms(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_string())
+        .collect()
+}
+
+/// Cuts a window of `content` around the first matching word and reports
+/// where the query terms occur within it.
+fn highlight_snippet(content: &str, terms: &[String]) -> (String, Vec<(usize, usize)>) {
+    let chars: Vec<char> = content
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect();
+
+    let mut matches = Vec::new();
+    let mut word_start = None;
+    for i in 0..=chars.len() {
+        let in_word = i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_');
+        match (in_word, word_start) {
+            (true, None) => word_start = Some(i),
+            (false, Some(start)) => {
+                let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+                if terms.contains(&word) {
+                    matches.push((start, i));
+                }
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let start = matches
+        .first()
+        .map(|(start, _)| start.saturating_sub(SNIPPET_CONTEXT))
+        .unwrap_or(0);
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < chars.len() { "…" } else { "" };
+    let offset = prefix.chars().count();
+    let snippet = format!(
+        "{}{}{}",
+        prefix,
+        chars[start..end].iter().collect::<String>(),
+        suffix
+    );
+    let highlights = matches
+        .into_iter()
+        .filter(|(s, e)| *s >= start && *e <= end)
+        .map(|(s, e)| (s - start + offset, e - start + offset))
+        .collect();
+
+    (snippet, highlights)
+}
+
+/// Shortens `title` to `MAX_TITLE_CHARS` on a character boundary.
+fn truncate_title(title: &str) -> String {
+    if title.chars().count() > MAX_TITLE_CHARS {
+        title.chars().take(MAX_TITLE_CHARS - 3).collect::<String>() + "..."
+    } else {
+        title.to_string()
+    }
+}
+
+/// Builds a title from the opening words of the first prompt, for when the
+/// model can't be reached.
+fn heuristic_title(prompt: &str) -> String {
+    let words: Vec<&str> = prompt
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .split_whitespace()
+        .take(HEURISTIC_TITLE_WORDS)
+        .collect();
+    let title = words
+        .join(" ")
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_string();
+
+    if title.is_empty() {
+        UNTITLED_SESSION_TITLE.to_string()
+    } else {
+        truncate_title(&title)
+    }
+}
+
 // Function to generate a title using the LLM
 async fn generate_title_with_llm(messages: &[FrontendMessage]) -> Result<String, String> {
     // Create a prompt for title generation
@@ -30,7 +148,7 @@ async fn generate_title_with_llm(messages: &[FrontendMessage]) -> Result<String,
     );
 
     // Use the same LLM service that handles chat to generate the title
-    let response = fetch_llm_response(&prompt).await?;
+    let response = fetch_llm_response(&prompt, 20).await?;
 
     // Clean up the response
     let title = response
@@ -38,15 +156,65 @@ async fn generate_title_with_llm(messages: &[FrontendMessage]) -> Result<String,
         .trim_matches('"')
         .trim_matches('.')
         .to_string();
+    if title.is_empty() {
+        return Err("Model returned an empty title".to_string());
+    }
 
-    Ok(if title.len() > 50 {
-        title[..47].to_string() + "..."
-    } else {
-        title
-    })
+    Ok(truncate_title(&title))
 }
 
-async fn fetch_llm_response(prompt: &str) -> Result<String, String> {
+/// Titles a session from its opening turns, falling back to a heuristic
+/// title when the model is unavailable.
+async fn title_session(db: &Database, session_id: &str) -> Result<String, String> {
+    let messages: Vec<FrontendMessage> = db
+        .get_active_branch(session_id)?
+        .into_iter()
+        .filter(|msg| msg.message.role != "system" && !msg.message.content.is_empty())
+        .take(2)
+        .map(|msg| FrontendMessage {
+            role: msg.message.role,
+            content: msg.message.content,
+        })
+        .collect();
+    let prompt = messages
+        .iter()
+        .find(|msg| msg.role == "user")
+        .ok_or_else(|| format!("Session {} has no prompt to title", session_id))?;
+
+    let title = match generate_title_with_llm(&messages).await {
+        Ok(title) => title,
+        Err(e) => {
+            println!(
+                "[title_session] Using fallback title for {}: {}",
+                session_id, e
+            );
+            heuristic_title(&prompt.content)
+        }
+    };
+    db.update_session_title(session_id, &title)?;
+    Ok(title)
+}
+
+/// Titles the given sessions one after another in the background, emitting
+/// `session_title_updated` as each one is done.
+fn spawn_title_generation(app_handle: tauri::AppHandle, session_ids: Vec<String>) {
+    tauri::async_runtime::spawn(async move {
+        let db = app_handle.state::<Database>();
+        for session_id in session_ids {
+            match title_session(&db, &session_id).await {
+                Ok(title) => {
+                    let _ = app_handle.emit(
+                        "session_title_updated",
+                        SessionTitleUpdate { session_id, title },
+                    );
+                }
+                Err(e) => println!("[spawn_title_generation] {}", e),
+            }
+        }
+    });
+}
+
+pub(crate) async fn fetch_llm_response(prompt: &str, max_tokens: u32) -> Result<String, String> {
     // Example using HTTP client to communicate with local llama.cpp server
     let client = reqwest::Client::new();
     let response = client
@@ -58,7 +226,7 @@ async fn fetch_llm_response(prompt: &str) -> Result<String, String> {
                     "content": prompt
                 }
             ],
-            "max_tokens": 20,
+            "max_tokens": max_tokens,
             "temperature": 0.7
         }))
         .send()
@@ -67,10 +235,10 @@ async fn fetch_llm_response(prompt: &str) -> Result<String, String> {
 
     let json: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
 
-    Ok(json["choices"][0]["message"]["content"]
+    json["choices"][0]["message"]["content"]
         .as_str()
-        .unwrap_or("Untitled Chat")
-        .to_string())
+        .map(|content| content.to_string())
+        .ok_or_else(|| "Model response had no content".to_string())
 }
 
 #[tauri::command]
@@ -80,76 +248,220 @@ pub async fn insert_message(
     session_id: String,
     message: String,
     response: String,
+    model: Option<String>,
 ) -> Result<(), String> {
     let db = app_handle.state::<Database>();
 
-    // Insert the message first
-    db.insert_message(&id, &session_id, &message, &response)?;
-
-    // If this is the first message in the session, generate title using LLM
-    let history = db.get_chat_history(&session_id)?;
-    if history.len() == 1 {
-        // This is the first message
-        let messages = vec![
-            FrontendMessage {
-                role: "user".to_string(),
-                content: message.clone(),
+    // Insert the user message first, followed by the reply if we already have one
+    db.insert_message(&NewMessage {
+        id: id.clone(),
+        session_id: session_id.clone(),
+        role: "user".to_string(),
+        content: message,
+        parent_id: active_leaf(&db, &session_id)?,
+        ..Default::default()
+    })?;
+    if !response.is_empty() {
+        db.upsert_response(
+            &id,
+            &response,
+            &ResponseMeta {
+                model,
+                ..Default::default()
             },
-            FrontendMessage {
-                role: "assistant".to_string(),
-                content: response.clone(),
-            },
-        ];
+        )?;
+    }
 
-        // Generate and save the AI-created title
-        if let Ok(title) = generate_title_with_llm(&messages).await {
-            db.update_session_title(&session_id, &title)?;
-        }
+    // Title the session in the background once it has its first prompt
+    let history = db.get_chat_history(&session_id)?;
+    if history.iter().filter(|msg| msg.role == "user").count() == 1 {
+        spawn_title_generation(app_handle.clone(), vec![session_id]);
     }
 
     Ok(())
 }
 
+/// Re-runs title generation for `session_ids`, or for every session still
+/// without a title. Returns how many sessions were queued; results arrive as
+/// `session_title_updated` events.
+#[tauri::command]
+pub fn regenerate_titles(
+    app_handle: tauri::AppHandle,
+    session_ids: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let session_ids = match session_ids {
+        Some(session_ids) => session_ids,
+        None => app_handle
+            .state::<Database>()
+            .get_session_info(true)?
+            .into_iter()
+            .filter(|session| session.title == UNTITLED_SESSION_TITLE)
+            .map(|session| session.id)
+            .collect(),
+    };
+
+    let count = session_ids.len();
+    spawn_title_generation(app_handle, session_ids);
+    Ok(count)
+}
+
+/// Appends a message with an explicit role (system prompts, tool calls,
+/// additional assistant turns) and returns its id.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn add_message(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    role: String,
+    content: String,
+    parent_id: Option<String>,
+    model: Option<String>,
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+) -> Result<String, String> {
+    if !["system", "user", "assistant", "tool"].contains(&role.as_str()) {
+        return Err(format!("Unknown message role: {}", role));
+    }
+
+    let db = app_handle.state::<Database>();
+    let parent_id = match parent_id {
+        Some(parent_id) => Some(parent_id),
+        None => active_leaf(&db, &session_id)?,
+    };
+    let id = uuid::Uuid::new_v4().to_string();
+    db.insert_message(&NewMessage {
+        id: id.clone(),
+        session_id,
+        role,
+        content,
+        parent_id,
+        model,
+        prompt_tokens,
+        completion_tokens,
+        latency_ms: None,
+    })?;
+    Ok(id)
+}
+
+/// Stores the finished reply to `id`. `latency_ms` is the time from sending
+/// the prompt to the last token and feeds the usage statistics.
 #[tauri::command]
 pub fn update_message_response(
     app_handle: tauri::AppHandle,
     id: String,
     response: String,
+    model: Option<String>,
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+    latency_ms: Option<i64>,
 ) -> Result<(), String> {
     let db = app_handle.state::<Database>();
-    db.update_message_response(&id, &response)
+    db.upsert_response(
+        &id,
+        &response,
+        &ResponseMeta {
+            model,
+            prompt_tokens,
+            completion_tokens,
+            latency_ms,
+        },
+    )
+}
+
+/// The message new turns in `session_id` continue from, if any.
+fn active_leaf(db: &Database, session_id: &str) -> Result<Option<String>, String> {
+    Ok(db
+        .get_active_branch(session_id)?
+        .pop()
+        .map(|branch| branch.message.id))
+}
+
+fn visible_branch(db: &Database, session_id: &str) -> Result<Vec<BranchMessage>, String> {
+    Ok(db
+        .get_active_branch(session_id)?
+        .into_iter()
+        .filter(|msg| msg.message.role == "system" || !msg.message.content.is_empty())
+        .collect())
 }
 
+/// Returns the active branch of the conversation. Each message carries
+/// `sibling_index`/`sibling_count` so the UI can offer switching between
+/// alternative answers and edits.
 #[tauri::command]
 pub fn get_chat_history(
     app_handle: tauri::AppHandle,
     session_id: String,
-) -> Result<Vec<FrontendMessage>, String> {
+) -> Result<Vec<BranchMessage>, String> {
     let db = app_handle.state::<Database>();
-    let history = db.get_chat_history(&session_id)?;
+    visible_branch(&db, &session_id)
+}
 
-    let frontend_history = history
-        .into_iter()
-        .flat_map(|db_msg| {
-            let user_message = FrontendMessage {
-                role: "user".to_string(),
-                content: db_msg.message,
-            };
-            if !db_msg.response.is_empty() {
-                vec![
-                    user_message,
-                    FrontendMessage {
-                        role: "assistant".to_string(),
-                        content: db_msg.response,
-                    },
-                ]
-            } else {
-                vec![user_message]
-            }
-        })
-        .collect();
+/// Starts a new answer alongside `message_id` (an assistant message) or to
+/// it (a user message) and returns the id of the empty reply, which the
+/// generated text is then stored to with `update_message_response`. The
+/// prompt to generate it from comes from `build_context`, as for a new
+/// message.
+#[tauri::command]
+pub fn regenerate_response(
+    app_handle: tauri::AppHandle,
+    message_id: String,
+) -> Result<String, String> {
+    let db = app_handle.state::<Database>();
+    let target = db.get_message(&message_id)?;
+    let parent_id = match target.role.as_str() {
+        "assistant" => target
+            .parent_id
+            .ok_or_else(|| format!("Message {} has nothing to answer", message_id))?,
+        _ => target.id,
+    };
 
-    Ok(frontend_history)
+    let id = uuid::Uuid::new_v4().to_string();
+    db.insert_message(&NewMessage {
+        id: id.clone(),
+        session_id: target.session_id,
+        role: "assistant".to_string(),
+        content: String::new(),
+        parent_id: Some(parent_id),
+        ..Default::default()
+    })?;
+    Ok(id)
+}
+
+/// Forks the conversation at a user message: the edited prompt is stored as
+/// a sibling of `message_id` and becomes the active branch. Returns its id.
+#[tauri::command]
+pub fn edit_message(
+    app_handle: tauri::AppHandle,
+    message_id: String,
+    content: String,
+) -> Result<String, String> {
+    let db = app_handle.state::<Database>();
+    let target = db.get_message(&message_id)?;
+    if target.role != "user" {
+        return Err("Only user messages can be edited".to_string());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    db.insert_message(&NewMessage {
+        id: id.clone(),
+        session_id: target.session_id,
+        role: "user".to_string(),
+        content,
+        parent_id: target.parent_id,
+        ..Default::default()
+    })?;
+    Ok(id)
+}
+
+/// Shows the branch that runs through `message_id` and returns it.
+#[tauri::command]
+pub fn switch_branch(
+    app_handle: tauri::AppHandle,
+    message_id: String,
+) -> Result<Vec<BranchMessage>, String> {
+    let db = app_handle.state::<Database>();
+    let session_id = db.switch_branch(&message_id)?;
+    visible_branch(&db, &session_id)
 }
 
 #[tauri::command]
@@ -163,11 +475,151 @@ pub fn update_session_title(
 }
 
 #[tauri::command]
-pub fn get_sessions(app_handle: tauri::AppHandle) -> Result<Vec<ChatSession>, String> {
+pub fn get_sessions(
+    app_handle: tauri::AppHandle,
+    include_archived: Option<bool>,
+) -> Result<Vec<ChatSession>, String> {
+    let db = app_handle.state::<Database>();
+    db.get_session_info(include_archived.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn create_session(
+    app_handle: tauri::AppHandle,
+    id: Option<String>,
+    title: Option<String>,
+) -> Result<String, String> {
+    let db = app_handle.state::<Database>();
+    let id = id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    db.create_session(&id, title.as_deref())?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn delete_session(app_handle: tauri::AppHandle, session_id: String) -> Result<(), String> {
+    let db = app_handle.state::<Database>();
+    db.delete_session(&session_id)
+}
+
+#[tauri::command]
+pub fn archive_session(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    archived: Option<bool>,
+) -> Result<(), String> {
+    let db = app_handle.state::<Database>();
+    db.set_session_flag(&session_id, SessionFlag::Archived, archived.unwrap_or(true))
+}
+
+#[tauri::command]
+pub fn pin_session(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    pinned: Option<bool>,
+) -> Result<(), String> {
     let db = app_handle.state::<Database>();
-    let sessions = db.get_session_info()?;
-    Ok(sessions
+    db.set_session_flag(&session_id, SessionFlag::Pinned, pinned.unwrap_or(true))
+}
+
+#[tauri::command]
+pub fn search_chats(
+    app_handle: tauri::AppHandle,
+    query: String,
+    session_id: Option<String>,
+    date_range: Option<DateRange>,
+    limit: Option<i64>,
+) -> Result<Vec<ChatSearchHit>, String> {
+    let terms = search_terms(&query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let db = app_handle.state::<Database>();
+    let (from, to) = date_range
+        .map(|range| (range.from, range.to))
+        .unwrap_or_default();
+    let hits = db.search_messages(
+        &query,
+        session_id.as_deref(),
+        from.as_deref(),
+        to.as_deref(),
+        limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+    )?;
+
+    Ok(hits
         .into_iter()
-        .map(|(id, title)| ChatSession { id, title })
+        .map(|hit| {
+            let (snippet, highlights) = highlight_snippet(&hit.content, &terms);
+            ChatSearchHit {
+                message_id: hit.message_id,
+                session_id: hit.session_id,
+                session_title: hit.session_title,
+                role: hit.role,
+                snippet,
+                highlights,
+                created_at: hit.created_at,
+                score: hit.score,
+            }
+        })
         .collect())
 }
+
+/// Usage statistics over the chat history, optionally limited to a date
+/// range.
+#[tauri::command]
+pub fn chat_stats(
+    app_handle: tauri::AppHandle,
+    date_range: Option<DateRange>,
+) -> Result<ChatStats, String> {
+    let db = app_handle.state::<Database>();
+    let (from, to) = date_range
+        .map(|range| (range.from, range.to))
+        .unwrap_or_default();
+    db.chat_stats(from.as_deref(), to.as_deref())
+}
+
+/// Returns the error that prevented the chat database from opening, if any.
+#[tauri::command]
+pub fn get_database_status(app_handle: tauri::AppHandle) -> Option<String> {
+    app_handle.state::<Database>().status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_terms_splits_on_punctuation_and_lowercases() {
+        assert_eq!(
+            search_terms("Rust's borrow-checker, snake_case!"),
+            vec!["rust", "s", "borrow", "checker", "snake_case"]
+        );
+    }
+
+    #[test]
+    fn highlight_snippet_marks_whole_word_matches() {
+        let terms = search_terms("rust");
+        let (snippet, highlights) = highlight_snippet("Rust and rusty\nRUST", &terms);
+        assert_eq!(snippet, "Rust and rusty RUST");
+        assert_eq!(highlights, vec![(0, 4), (15, 19)]);
+    }
+
+    #[test]
+    fn highlight_snippet_windows_long_content_around_first_match() {
+        let content = format!("{} needle {}", "a ".repeat(100), "b ".repeat(200));
+        let (snippet, highlights) = highlight_snippet(&content, &search_terms("needle"));
+        assert!(snippet.starts_with('…'));
+        assert!(snippet.ends_with('…'));
+        assert_eq!(snippet.chars().count(), SNIPPET_LENGTH + 2);
+        let chars: Vec<char> = snippet.chars().collect();
+        let (start, end) = highlights[0];
+        assert_eq!(chars[start..end].iter().collect::<String>(), "needle");
+    }
+
+    #[test]
+    fn highlight_snippet_without_match_starts_at_beginning() {
+        let (snippet, highlights) = highlight_snippet("nothing here", &search_terms("absent"));
+        assert_eq!(snippet, "nothing here");
+        assert!(highlights.is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::db::{
+    Database, ImportConflict, ImportOutcome, Message, SessionExport, UNTITLED_SESSION_TITLE,
+};
+use crate::path_guard::authorize;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tauri::Manager;
+
+const EXPORT_VERSION: u32 = 1;
+const ARCHIVE_BUNDLE_NAME: &str = "sessions.json";
+
+/// The JSON document produced by exports and read back by imports.
+#[derive(Serialize, Deserialize)]
+pub struct ExportBundle {
+    pub version: u32,
+    pub exported_at: String,
+    pub sessions: Vec<SessionExport>,
+}
+
+impl ExportBundle {
+    fn new(sessions: Vec<SessionExport>) -> Self {
+        ExportBundle {
+            version: EXPORT_VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            sessions,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+    pub replaced: usize,
+    /// Sessions whose id was taken and were imported under a new one
+    pub renamed: usize,
+}
+
+fn session_title(session: &SessionExport) -> &str {
+    session
+        .title
+        .as_deref()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or(UNTITLED_SESSION_TITLE)
+}
+
+fn role_label(role: &str) -> String {
+    let mut chars = role.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The conversation as the session currently shows it: the active branch,
+/// without the empty placeholders regeneration leaves behind. Abandoned
+/// branches are only kept in JSON exports.
+fn transcript(db: &Database, session_id: &str) -> Result<Vec<Message>, String> {
+    Ok(db
+        .get_active_branch(session_id)?
+        .into_iter()
+        .map(|branch| branch.message)
+        .filter(|message| !message.content.trim().is_empty())
+        .collect())
+}
+
+fn to_markdown(session: &SessionExport, messages: &[Message]) -> String {
+    let mut out = format!(
+        "# {}\n\n_Created {}_\n",
+        session_title(session),
+        session.created_at
+    );
+    for message in messages {
+        out.push_str(&format!("\n## {}", role_label(&message.role)));
+        if let Some(model) = &message.model {
+            out.push_str(&format!(" ({})", model));
+        }
+        out.push_str(&format!("\n\n{}\n", message.content.trim_end()));
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_html(session: &SessionExport, messages: &[Message]) -> String {
+    let title = escape_html(session_title(session));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; max-width: 800px; margin: 2em auto; }}\n\
+         .message {{ margin: 1em 0; padding: 0.75em 1em; border-radius: 6px; background: #f4f4f5; }}\n\
+         .user {{ background: #e0ecff; }}\n\
+         .role {{ font-weight: bold; margin-bottom: 0.5em; }}\n\
+         .content {{ white-space: pre-wrap; }}\n</style>\n</head>\n<body>\n<h1>{0}</h1>\n\
+         <p><em>Created {1}</em></p>\n",
+        title,
+        escape_html(&session.created_at)
+    );
+    for message in messages {
+        out.push_str(&format!(
+            "<div class=\"message {}\">\n<div class=\"role\">{}</div>\n<div class=\"content\">{}</div>\n</div>\n",
+            escape_html(&message.role),
+            escape_html(&role_label(&message.role)),
+            escape_html(&message.content)
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// File name for a session inside the archive, readable but still unique.
+fn archive_file_name(session: &SessionExport) -> String {
+    let slug: String = session_title(session)
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .take(8)
+        .collect::<Vec<_>>()
+        .join("-");
+    let id: String = session.id.chars().take(8).collect();
+    format!("markdown/{}-{}.md", slug, id)
+}
+
+fn append_file(
+    archive: &mut tar::Builder<GzEncoder<File>>,
+    name: &str,
+    contents: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, contents)
+}
+
+fn read_bundle(path: &Path) -> Result<ExportBundle, String> {
+    let name = path.to_string_lossy().to_lowercase();
+    let file = File::open(path).map_err(|e| format!("Failed to open import file: {}", e))?;
+
+    let json = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        let mut json = None;
+        for entry in archive
+            .entries()
+            .map_err(|e| format!("Failed to read archive: {}", e))?
+        {
+            let mut entry = entry.map_err(|e| format!("Failed to read archive: {}", e))?;
+            let is_bundle = entry
+                .path()
+                .map(|p| p == Path::new(ARCHIVE_BUNDLE_NAME))
+                .unwrap_or(false);
+            if is_bundle {
+                let mut contents = String::new();
+                entry
+                    .read_to_string(&mut contents)
+                    .map_err(|e| format!("Failed to read archive: {}", e))?;
+                json = Some(contents);
+                break;
+            }
+        }
+        json.ok_or_else(|| format!("Archive does not contain {}", ARCHIVE_BUNDLE_NAME))?
+    } else {
+        let mut contents = String::new();
+        std::io::BufReader::new(file)
+            .read_to_string(&mut contents)
+            .map_err(|e| format!("Failed to read import file: {}", e))?;
+        contents
+    };
+
+    let bundle: ExportBundle =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid export file: {}", e))?;
+    if bundle.version > EXPORT_VERSION {
+        return Err(format!(
+            "Export version {} is newer than this app supports",
+            bundle.version
+        ));
+    }
+    Ok(bundle)
+}
+
+#[tauri::command]
+pub fn export_session(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    format: Option<String>,
+) -> Result<String, String> {
+    let db = app_handle.state::<Database>();
+    let session = db.export_session(&session_id)?;
+
+    match format.as_deref().unwrap_or("markdown") {
+        "markdown" | "md" => Ok(to_markdown(&session, &transcript(&db, &session_id)?)),
+        "html" => Ok(to_html(&session, &transcript(&db, &session_id)?)),
+        "json" => serde_json::to_string_pretty(&ExportBundle::new(vec![session]))
+            .map_err(|e| format!("Failed to serialize session: {}", e)),
+        other => Err(format!("Unsupported export format: {}", other)),
+    }
+}
+
+/// Writes every session to a `.tar.gz` holding `sessions.json` for
+/// re-import plus a Markdown copy of each conversation.
+#[tauri::command]
+pub async fn export_all_sessions(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<usize, String> {
+    let target = authorize(&app_handle, &path, "write").await?;
+    let db = app_handle.state::<Database>();
+    let sessions = db
+        .get_session_info(true)?
+        .iter()
+        .map(|session| db.export_session(&session.id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = sessions.len();
+
+    let file = File::create(&target).map_err(|e| format!("Failed to create archive: {}", e))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for session in &sessions {
+        let markdown = to_markdown(session, &transcript(&db, &session.id)?);
+        let name = archive_file_name(session);
+        append_file(&mut archive, &name, markdown.as_bytes())
+            .map_err(|e| format!("Failed to write archive: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(&ExportBundle::new(sessions))
+        .map_err(|e| format!("Failed to serialize sessions: {}", e))?;
+    append_file(&mut archive, ARCHIVE_BUNDLE_NAME, &json)
+        .map_err(|e| format!("Failed to write archive: {}", e))?;
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to write archive: {}", e))?;
+
+    println!(
+        "[export_all_sessions] Exported {} sessions to {}",
+        count, path
+    );
+    Ok(count)
+}
+
+/// Imports sessions from a JSON export or a `.tar.gz` archive. `on_conflict`
+/// decides what happens to sessions whose id already exists: "skip"
+/// (default), "replace", or "duplicate" to import them under a new id.
+#[tauri::command]
+pub async fn import_sessions(
+    app_handle: tauri::AppHandle,
+    path: String,
+    on_conflict: Option<String>,
+) -> Result<ImportSummary, String> {
+    let conflict = match on_conflict.as_deref().unwrap_or("skip") {
+        "skip" => ImportConflict::Skip,
+        "replace" => ImportConflict::Replace,
+        "duplicate" => ImportConflict::Duplicate,
+        other => return Err(format!("Unknown conflict mode: {}", other)),
+    };
+
+    let source = authorize(&app_handle, &path, "read").await?;
+    let bundle = read_bundle(&source)?;
+    let db = app_handle.state::<Database>();
+
+    let mut summary = ImportSummary::default();
+    for session in &bundle.sessions {
+        match db.import_session(session, conflict)? {
+            ImportOutcome::Imported => summary.imported += 1,
+            ImportOutcome::Skipped => summary.skipped += 1,
+            ImportOutcome::Replaced => summary.replaced += 1,
+            ImportOutcome::Duplicated => summary.renamed += 1,
+        }
+    }
+
+    println!("[import_sessions] {:?}", summary);
+    Ok(summary)
+}
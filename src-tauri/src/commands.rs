@@ -183,7 +183,7 @@ pub async fn initialize_project(prompt: String, app_handle: AppHandle) -> Result
         if let FileOpResult {
             success: false,
             message,
-        } = create_dir(dir).await
+        } = create_dir(app_handle.clone(), dir).await
         {
             return Err(format!("Failed to create directory: {}", message));
         }
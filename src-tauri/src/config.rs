@@ -1,3 +1,4 @@
+use crate::path_guard::{authorize, PathGuard};
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::Read;
@@ -22,39 +23,53 @@ pub fn get_default_project_directory() -> PathBuf {
     }
 }
 
-pub fn get_project_directory(app_handle: &AppHandle) -> Result<PathBuf, String> {
-    let app_data_dir = app_handle
+fn config_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    app_handle
         .path()
         .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    let config_path = app_data_dir.join("project_config.json");
+        .map(|dir| dir.join("project_config.json"))
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
 
-    if config_path.exists() {
-        let mut file =
-            File::open(&config_path).map_err(|e| format!("Failed to open config: {}", e))?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| format!("Failed to read config: {}", e))?;
-        let config: ProjectConfig = serde_json::from_str(&contents)
-            .map_err(|e| format!("Failed to parse config: {}", e))?;
-        Ok(config.project_directory)
-    } else {
-        let default_dir = get_default_project_directory();
-        let config = ProjectConfig {
-            project_directory: default_dir.clone(),
-        };
+/// The configured project directory, or None if none was saved yet. Unlike
+/// `get_project_directory`, this never writes the config.
+pub fn stored_project_directory(app_handle: &AppHandle) -> Result<Option<PathBuf>, String> {
+    let config_path = config_path(app_handle)?;
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    let mut file = File::open(&config_path).map_err(|e| format!("Failed to open config: {}", e))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| format!("Failed to read config: {}", e))?;
+    let config: ProjectConfig =
+        serde_json::from_str(&contents).map_err(|e| format!("Failed to parse config: {}", e))?;
+    Ok(Some(config.project_directory))
+}
+
+pub fn get_project_directory(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    if let Some(dir) = stored_project_directory(app_handle)? {
+        return Ok(dir);
+    }
+
+    let config_path = config_path(app_handle)?;
+    let default_dir = get_default_project_directory();
+    let config = ProjectConfig {
+        project_directory: default_dir.clone(),
+    };
+    if let Some(app_data_dir) = config_path.parent() {
         fs::create_dir_all(app_data_dir)
             .map_err(|e| format!("Failed to create app data dir: {}", e))?;
-        let mut file = File::create(&config_path)
-            .map_err(|e| format!("Failed to create config file: {}", e))?;
-        serde_json::to_writer(&mut file, &config)
-            .map_err(|e| format!("Failed to write config: {}", e))?;
-        Ok(default_dir)
     }
+    let mut file =
+        File::create(&config_path).map_err(|e| format!("Failed to create config file: {}", e))?;
+    serde_json::to_writer(&mut file, &config)
+        .map_err(|e| format!("Failed to write config: {}", e))?;
+    Ok(default_dir)
 }
 
 #[tauri::command]
-pub fn set_project_directory(app_handle: AppHandle, directory: String) -> Result<(), String> {
+pub async fn set_project_directory(app_handle: AppHandle, directory: String) -> Result<(), String> {
     let dir_path = PathBuf::from(&directory);
     if !dir_path.is_absolute() {
         return Err("Directory must be an absolute path".to_string());
@@ -71,7 +86,13 @@ pub fn set_project_directory(app_handle: AppHandle, directory: String) -> Result
         .map_err(|e| format!("Failed to get app data dir: {}", e))?;
     let config_path = app_data_dir.join("project_config.json");
 
+    // A folder outside the allowed ones has to be confirmed by the user
+    // before it becomes the project directory
+    authorize(&app_handle, &directory, "open").await?;
     fs::create_dir_all(&dir_path).map_err(|e| format!("Failed to create directory: {}", e))?;
+    app_handle
+        .state::<PathGuard>()
+        .allow(&app_handle, &dir_path)?;
 
     let config = ProjectConfig {
         project_directory: dir_path,
@@ -0,0 +1,421 @@
+use crate::chat::{fetch_llm_response, FrontendMessage};
+use crate::db::{BranchMessage, Database};
+use crate::prompts::session_system_prompt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+use tokenizers::Tokenizer;
+
+// Rough per-message cost of the role markers chat templates add
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+// Length limit for a generated summary, also reserved in the context for it
+const SUMMARY_MAX_TOKENS: usize = 256;
+// Room left for the instructions wrapped around text being summarized
+const SUMMARY_PROMPT_TOKENS: usize = 64;
+// Estimate used when the active model has no tokenizer.json next to it
+const CHARS_PER_TOKEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ContextBudget {
+    /// Context size the model was loaded with
+    pub context_size: usize,
+    /// Tokens kept free for the model's reply
+    pub reserve_tokens: usize,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        ContextBudget {
+            context_size: 2048,
+            reserve_tokens: 256,
+        }
+    }
+}
+
+impl ContextBudget {
+    /// Fills in defaults for the sizes left out, rejecting a reserve that
+    /// would leave no room for the prompt.
+    pub fn new(context_size: Option<usize>, reserve_tokens: Option<usize>) -> Result<Self, String> {
+        let defaults = ContextBudget::default();
+        let budget = ContextBudget {
+            context_size: context_size.unwrap_or(defaults.context_size),
+            reserve_tokens: reserve_tokens.unwrap_or(defaults.reserve_tokens),
+        };
+        if budget.reserve_tokens >= budget.context_size {
+            return Err("Reserved tokens must be smaller than the context size".to_string());
+        }
+        Ok(budget)
+    }
+}
+
+#[derive(Default)]
+pub struct ContextModel {
+    pub name: Option<String>,
+    pub tokenizer: Option<Arc<Tokenizer>>,
+    pub budget: ContextBudget,
+}
+
+pub struct ContextState {
+    pub model: Mutex<ContextModel>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContextModelInfo {
+    pub model: Option<String>,
+    pub budget: ContextBudget,
+    /// False when token counts are estimated from text length
+    pub exact_counts: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssembledContext {
+    pub messages: Vec<FrontendMessage>,
+    pub prompt_tokens: usize,
+    /// Older messages represented by the session summary instead
+    pub summarized_messages: usize,
+    /// Older messages left out because no summary could be produced
+    pub dropped_messages: usize,
+}
+
+#[derive(Clone)]
+struct TokenCounter(Option<Arc<Tokenizer>>);
+
+impl TokenCounter {
+    fn count(&self, text: &str) -> usize {
+        let estimate = || text.chars().count().div_ceil(CHARS_PER_TOKEN);
+        match &self.0 {
+            Some(tokenizer) => tokenizer
+                .encode(text, false)
+                .map(|encoding| encoding.len())
+                .unwrap_or_else(|_| estimate()),
+            None => estimate(),
+        }
+    }
+
+    fn message(&self, content: &str) -> usize {
+        self.count(content) + MESSAGE_OVERHEAD_TOKENS
+    }
+}
+
+fn counter(app_handle: &tauri::AppHandle) -> Result<(TokenCounter, ContextBudget), String> {
+    let state = app_handle.state::<ContextState>();
+    let model = state.model.lock().map_err(|e| e.to_string())?;
+    Ok((TokenCounter(model.tokenizer.clone()), model.budget))
+}
+
+/// Looks for `<model>.tokenizer.json`, then `tokenizer.json`, beside a
+/// GGUF model file.
+fn find_tokenizer(model_path: &Path) -> Option<PathBuf> {
+    let dir = model_path.parent()?;
+    let stem = model_path.file_stem()?.to_string_lossy();
+    [
+        dir.join(format!("{}.tokenizer.json", stem)),
+        dir.join("tokenizer.json"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Index of the first of `turns` that still fits in `available` tokens when
+/// filling from the newest message backwards. The newest message is always
+/// kept, and the window never opens with a reply whose prompt was cut.
+fn recent_start(turns: &[BranchMessage], counter: &TokenCounter, available: usize) -> usize {
+    let mut used = 0;
+    let mut start = turns.len();
+    while start > 0 {
+        let cost = counter.message(&turns[start - 1].message.content);
+        if used + cost > available && start < turns.len() {
+            break;
+        }
+        used += cost;
+        start -= 1;
+    }
+    while start > 0 && start + 1 < turns.len() && turns[start].message.role != "user" {
+        start += 1;
+    }
+    start
+}
+
+async fn request_summary(previous: Option<&str>, lines: &[String]) -> Result<String, String> {
+    let mut prompt = String::from(
+        "Summarize the conversation below so it can replace the original messages as context. \
+         Keep facts, decisions, names, code identifiers and open questions. Be concise.\n\n",
+    );
+    if let Some(previous) = previous {
+        prompt.push_str(&format!("Summary so far:\n{}\n\n", previous));
+    }
+    prompt.push_str(&format!("Conversation:\n{}", lines.join("\n")));
+
+    let summary = fetch_llm_response(&prompt, SUMMARY_MAX_TOKENS as u32).await?;
+    Ok(summary.trim().to_string())
+}
+
+/// Brings the session summary up to date with `older`, extending the stored
+/// one when it covers a prefix of them, and feeding the model in chunks that
+/// fit its context.
+async fn summarize(
+    db: &Database,
+    session_id: &str,
+    older: &[BranchMessage],
+    counter: &TokenCounter,
+    budget: ContextBudget,
+) -> Result<String, String> {
+    let Some(last) = older.last() else {
+        return Err("Nothing to summarize".to_string());
+    };
+
+    let (mut summary, pending) = match db.get_session_summary(session_id)? {
+        Some((summary, through)) => match older.iter().position(|m| m.message.id == through) {
+            Some(pos) => (Some(summary), &older[pos + 1..]),
+            None => (None, older),
+        },
+        None => (None, older),
+    };
+    if pending.is_empty() {
+        if let Some(summary) = summary {
+            return Ok(summary);
+        }
+    }
+
+    let chunk_budget = budget
+        .context_size
+        .saturating_sub(SUMMARY_MAX_TOKENS + SUMMARY_PROMPT_TOKENS);
+    let mut lines = Vec::new();
+    let mut used = summary.as_deref().map_or(0, |s| counter.count(s));
+    for message in pending {
+        let line = format!("{}: {}", message.message.role, message.message.content);
+        let cost = counter.count(&line);
+        if !lines.is_empty() && used + cost > chunk_budget {
+            let next = request_summary(summary.as_deref(), &lines).await?;
+            used = counter.count(&next);
+            summary = Some(next);
+            lines.clear();
+        }
+        used += cost;
+        lines.push(line);
+    }
+    let summary = request_summary(summary.as_deref(), &lines).await?;
+
+    db.set_session_summary(session_id, &summary, &last.message.id)?;
+    println!(
+        "[build_context] Summarized {} messages of session {}",
+        older.len(),
+        session_id
+    );
+    Ok(summary)
+}
+
+/// Switches token counting to the model at `model_path`. Its tokenizer is
+/// read from `tokenizer_path` or found beside the model; without one, counts
+/// are estimated.
+#[tauri::command]
+pub fn set_context_model(
+    app_handle: tauri::AppHandle,
+    model_path: String,
+    context_size: Option<usize>,
+    reserve_tokens: Option<usize>,
+    tokenizer_path: Option<String>,
+) -> Result<ContextModelInfo, String> {
+    let tokenizer_path = tokenizer_path
+        .map(PathBuf::from)
+        .or_else(|| find_tokenizer(Path::new(&model_path)));
+    let tokenizer = match tokenizer_path {
+        Some(path) => Some(Arc::new(
+            Tokenizer::from_file(&path).map_err(|e| format!("Failed to load tokenizer: {}", e))?,
+        )),
+        None => {
+            println!(
+                "[set_context_model] No tokenizer found for {}, estimating token counts",
+                model_path
+            );
+            None
+        }
+    };
+
+    let budget = ContextBudget::new(context_size, reserve_tokens)?;
+
+    let state = app_handle.state::<ContextState>();
+    let mut model = state.model.lock().map_err(|e| e.to_string())?;
+    model.name = Path::new(&model_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+    model.tokenizer = tokenizer;
+    model.budget = budget;
+
+    Ok(ContextModelInfo {
+        model: model.name.clone(),
+        budget: model.budget,
+        exact_counts: model.tokenizer.is_some(),
+    })
+}
+
+#[tauri::command]
+pub fn count_tokens(app_handle: tauri::AppHandle, text: String) -> Result<usize, String> {
+    let (counter, _) = counter(&app_handle)?;
+    Ok(counter.count(&text))
+}
+
+/// Assembles the messages to send for the session's next turn: the
+/// session's preset and other system prompts, a summary of turns that no
+/// longer fit, and as many of the latest turns as the budget allows.
+/// `open_file` and `language` fill the preset's template variables. Called
+/// after the prompt is stored with `insert_message`, or after
+/// `regenerate_response`, whose empty reply is left out.
+#[tauri::command]
+pub async fn build_context(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    open_file: Option<String>,
+    language: Option<String>,
+) -> Result<AssembledContext, String> {
+    let (counter, budget) = counter(&app_handle)?;
+    let db = app_handle.state::<Database>();
+
+    let (stored_system, turns): (Vec<_>, Vec<_>) = db
+        .get_active_branch(&session_id)?
+        .into_iter()
+        .filter(|m| !m.message.content.is_empty())
+        .partition(|m| m.message.role == "system");
+
+    let preset = session_system_prompt(
+        &app_handle,
+        &session_id,
+        open_file.as_deref(),
+        language.as_deref(),
+    )?;
+    let system: Vec<String> = preset
+        .into_iter()
+        .chain(stored_system.into_iter().map(|m| m.message.content))
+        .collect();
+
+    let system_tokens: usize = system.iter().map(|content| counter.message(content)).sum();
+    let available = budget
+        .context_size
+        .saturating_sub(budget.reserve_tokens + system_tokens);
+
+    let mut split = recent_start(&turns, &counter, available);
+    if split > 0 {
+        // Make room for the summary that will stand in for the older turns
+        split = recent_start(
+            &turns,
+            &counter,
+            available.saturating_sub(SUMMARY_MAX_TOKENS + MESSAGE_OVERHEAD_TOKENS),
+        );
+    }
+    let (older, recent) = turns.split_at(split);
+
+    let summary = if older.is_empty() {
+        None
+    } else {
+        summarize(&db, &session_id, older, &counter, budget)
+            .await
+            .map_err(|e| println!("[build_context] Failed to summarize older turns: {}", e))
+            .ok()
+    };
+
+    let mut messages: Vec<FrontendMessage> = system
+        .into_iter()
+        .map(|content| FrontendMessage {
+            role: "system".to_string(),
+            content,
+        })
+        .collect();
+    if let Some(summary) = &summary {
+        messages.push(FrontendMessage {
+            role: "system".to_string(),
+            content: format!("Summary of the earlier conversation:\n{}", summary),
+        });
+    }
+    messages.extend(recent.iter().map(|m| FrontendMessage {
+        role: m.message.role.clone(),
+        content: m.message.content.clone(),
+    }));
+
+    let prompt_tokens = messages.iter().map(|m| counter.message(&m.content)).sum();
+    let (summarized_messages, dropped_messages) = match summary {
+        Some(_) => (older.len(), 0),
+        None => (0, older.len()),
+    };
+    Ok(AssembledContext {
+        messages,
+        prompt_tokens,
+        summarized_messages,
+        dropped_messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Message;
+
+    fn turn(id: &str, role: &str, content: &str) -> BranchMessage {
+        BranchMessage {
+            message: Message {
+                id: id.to_string(),
+                session_id: "s".to_string(),
+                role: role.to_string(),
+                content: content.to_string(),
+                parent_id: None,
+                model: None,
+                prompt_tokens: None,
+                completion_tokens: None,
+                latency_ms: None,
+                created_at: String::new(),
+            },
+            sibling_index: 0,
+            sibling_count: 1,
+        }
+    }
+
+    // Four exchanges whose messages each cost 4 estimated tokens plus overhead
+    fn conversation() -> Vec<BranchMessage> {
+        let text = "x".repeat(4 * CHARS_PER_TOKEN);
+        vec![
+            turn("u1", "user", &text),
+            turn("a1", "assistant", &text),
+            turn("u2", "user", &text),
+            turn("a2", "assistant", &text),
+        ]
+    }
+
+    const MESSAGE_COST: usize = 4 + MESSAGE_OVERHEAD_TOKENS;
+
+    #[test]
+    fn recent_start_keeps_everything_that_fits() {
+        let counter = TokenCounter(None);
+        assert_eq!(recent_start(&conversation(), &counter, 4 * MESSAGE_COST), 0);
+        assert_eq!(recent_start(&conversation(), &counter, 2 * MESSAGE_COST), 2);
+    }
+
+    #[test]
+    fn recent_start_does_not_open_with_an_orphaned_reply() {
+        let counter = TokenCounter(None);
+        // Room for a1, u2 and a2, but a1's prompt would be cut
+        assert_eq!(recent_start(&conversation(), &counter, 3 * MESSAGE_COST), 2);
+    }
+
+    #[test]
+    fn recent_start_always_keeps_the_newest_message() {
+        let counter = TokenCounter(None);
+        assert_eq!(recent_start(&conversation(), &counter, 0), 3);
+        assert_eq!(recent_start(&[], &counter, 100), 0);
+    }
+
+    #[test]
+    fn budget_uses_defaults_for_missing_sizes() {
+        let budget = ContextBudget::new(Some(8192), None).unwrap();
+        assert_eq!(budget.context_size, 8192);
+        assert_eq!(
+            budget.reserve_tokens,
+            ContextBudget::default().reserve_tokens
+        );
+    }
+
+    #[test]
+    fn budget_rejects_reserve_that_fills_the_context() {
+        assert!(ContextBudget::new(Some(512), Some(512)).is_err());
+        assert!(ContextBudget::new(Some(128), None).is_err());
+    }
+}
@@ -1,116 +1,547 @@
+use crate::migrations::run_migrations;
 use duckdb::Connection;
+use std::collections::HashMap;
 use std::fs;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 use tauri::{AppHandle, Manager};
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Message {
     pub id: String,
     pub session_id: String,
-    pub message: String,
-    pub response: String,
-    pub timestamp: String,
+    pub role: String,
+    pub content: String,
+    pub parent_id: Option<String>,
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Default)]
+pub struct NewMessage {
+    pub id: String,
+    pub session_id: String,
+    pub role: String,
+    pub content: String,
+    pub parent_id: Option<String>,
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+}
+
+/// Generation details stored alongside an assistant reply.
+#[derive(Debug, Default)]
+pub struct ResponseMeta {
+    pub model: Option<String>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub latency_ms: Option<i64>,
+}
+
+const MESSAGE_COLUMNS: &str = "id, session_id, role, content, parent_id, model, prompt_tokens, completion_tokens, latency_ms, CAST(created_at AS VARCHAR) AS created_at";
+
+/// Title shown for sessions that haven't been named yet.
+pub const UNTITLED_SESSION_TITLE: &str = "Untitled Chat";
+
+fn title_or_untitled(title: Option<String>) -> String {
+    title.unwrap_or_else(|| UNTITLED_SESSION_TITLE.to_string())
+}
+
+// Splits message text into search terms; must match 0004_message_search_index.sql
+const TERM_SEPARATOR: &str = r"[^\p{L}\p{N}_]+";
+
+/// Rebuilds the search terms of the messages matching `filter`, a condition
+/// on `messages` taking a single parameter.
+fn reindex_messages(conn: &Connection, filter: &str, param: &str) -> duckdb::Result<()> {
+    conn.execute(
+        &format!(
+            "DELETE FROM message_terms WHERE message_id IN (SELECT id FROM messages WHERE {})",
+            filter
+        ),
+        [param],
+    )?;
+    conn.execute(
+        &format!(
+            "INSERT INTO message_terms (message_id, term, tf)
+             SELECT id, term, COUNT(*)
+             FROM (
+                SELECT id, unnest(regexp_split_to_array(lower(content), '{}')) AS term
+                FROM messages WHERE {}
+             )
+             WHERE term <> ''
+             GROUP BY id, term",
+            TERM_SEPARATOR, filter
+        ),
+        [param],
+    )?;
+    Ok(())
+}
+
+fn message_from_row(row: &duckdb::Row) -> duckdb::Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        parent_id: row.get(4)?,
+        model: row.get(5)?,
+        prompt_tokens: row.get(6)?,
+        completion_tokens: row.get(7)?,
+        latency_ms: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+/// A message on the active branch, with its position among the alternative
+/// versions that share its parent.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BranchMessage {
+    #[serde(flatten)]
+    pub message: Message,
+    pub sibling_index: usize,
+    pub sibling_count: usize,
+}
+
+/// Walks from `leaf` (or the newest message) up to the root. `messages` must
+/// be in creation order.
+fn branch_path(messages: &[Message], leaf: Option<&str>) -> Vec<BranchMessage> {
+    let by_id: HashMap<&str, &Message> = messages.iter().map(|m| (m.id.as_str(), m)).collect();
+    let mut current = leaf
+        .and_then(|id| by_id.get(id).copied())
+        .or_else(|| messages.last());
+
+    let mut path = Vec::new();
+    while let Some(message) = current {
+        let siblings: Vec<&Message> = messages
+            .iter()
+            .filter(|m| m.parent_id == message.parent_id)
+            .collect();
+        path.push(BranchMessage {
+            message: message.clone(),
+            sibling_index: siblings
+                .iter()
+                .position(|m| m.id == message.id)
+                .unwrap_or(0),
+            sibling_count: siblings.len(),
+        });
+        current = message
+            .parent_id
+            .as_deref()
+            .and_then(|id| by_id.get(id).copied());
+        // Guards against a corrupted parent chain looping forever
+        if path.len() > messages.len() {
+            break;
+        }
+    }
+    path.reverse();
+    path
+}
+
+#[derive(Debug)]
+pub struct SearchHit {
+    pub message_id: String,
+    pub session_id: String,
+    pub session_title: String,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+    pub score: f64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ChatSession {
+    pub id: String,
+    pub title: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub message_count: i64,
+    pub last_message: Option<String>,
+    pub pinned: bool,
+    pub archived: bool,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct DailyActivity {
+    pub day: String,
+    pub user_messages: i64,
+    pub assistant_messages: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ModelUsage {
+    pub model: String,
+    pub responses: i64,
+    pub avg_latency_ms: Option<f64>,
+    pub avg_response_length: Option<f64>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub tokens_per_second: Option<f64>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SessionActivity {
+    pub session_id: String,
+    pub title: String,
+    pub message_count: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct HourlyActivity {
+    pub hour: i64,
+    pub messages: i64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ChatStats {
+    pub total_messages: i64,
+    pub total_responses: i64,
+    /// Average length of assistant replies in characters
+    pub avg_response_length: Option<f64>,
+    pub messages_per_day: Vec<DailyActivity>,
+    pub models: Vec<ModelUsage>,
+    pub top_sessions: Vec<SessionActivity>,
+    pub busiest_hours: Vec<HourlyActivity>,
+}
+
+// Restricts statistics to messages created between $1 and $2, either of
+// which may be NULL
+const STATS_SCOPE: &str = "WITH scoped AS (
+    SELECT * FROM messages
+    WHERE (CAST($1 AS TIMESTAMP) IS NULL OR created_at >= CAST($1 AS TIMESTAMP))
+        AND (CAST($2 AS TIMESTAMP) IS NULL OR created_at <= CAST($2 AS TIMESTAMP))
+)";
+const TOP_SESSIONS_LIMIT: i64 = 10;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PromptPreset {
+    pub id: String,
+    pub name: String,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+const PRESET_COLUMNS: &str =
+    "id, name, content, CAST(created_at AS VARCHAR), CAST(updated_at AS VARCHAR)";
+
+fn preset_from_row(row: &duckdb::Row) -> duckdb::Result<PromptPreset> {
+    Ok(PromptPreset {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        content: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+/// A session together with all of its messages, as exported and imported.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SessionExport {
+    pub id: String,
+    pub title: Option<String>,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    pub messages: Vec<Message>,
+}
+
+/// What to do when an imported session id already exists.
+#[derive(Clone, Copy)]
+pub enum ImportConflict {
+    Skip,
+    Replace,
+    Duplicate,
+}
+
+pub enum ImportOutcome {
+    Imported,
+    Skipped,
+    Replaced,
+    Duplicated,
+}
+
+pub enum SessionFlag {
+    Pinned,
+    Archived,
 }
 
 pub struct Database {
-    duckdb_conn: Arc<Mutex<Connection>>,
+    // Holds the reason the database could not be opened, so every chat
+    // command can report it instead of the app failing to start
+    duckdb_conn: Result<Arc<Mutex<Connection>>, String>,
 }
 
 impl Database {
     pub fn init(app_handle: &AppHandle) -> Result<Self, String> {
-        let app_dir = app_handle.path().app_data_dir().unwrap();
+        let app_dir = app_handle
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to get app data dir: {}", e))?;
         fs::create_dir_all(&app_dir).map_err(|e| e.to_string())?;
 
         let duckdb_path = app_dir.join("chat_history.duckdb");
-        let duckdb_conn = Connection::open(&duckdb_path).map_err(|e| e.to_string())?;
-        duckdb_conn
-            .execute_batch(
-                "CREATE TABLE IF NOT EXISTS messages (
-                    id VARCHAR PRIMARY KEY,
-                    session_id VARCHAR,
-                    message VARCHAR,
-                    response VARCHAR,
-                    timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                );
-                CREATE INDEX IF NOT EXISTS idx_session ON messages (session_id);
-                
-                CREATE TABLE IF NOT EXISTS chat_sessions (
-                    session_id VARCHAR PRIMARY KEY,
-                    title VARCHAR,
-                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-                );",
-            )
-            .map_err(|e| e.to_string())?;
+        let mut duckdb_conn = Connection::open(&duckdb_path)
+            .map_err(|e| format!("Failed to open chat database: {}", e))?;
+        run_migrations(&mut duckdb_conn, &duckdb_path)?;
 
         Ok(Database {
-            duckdb_conn: Arc::new(Mutex::new(duckdb_conn)),
+            duckdb_conn: Ok(Arc::new(Mutex::new(duckdb_conn))),
         })
     }
 
-    pub fn insert_message(
+    pub fn unavailable(error: String) -> Self {
+        Database {
+            duckdb_conn: Err(error),
+        }
+    }
+
+    pub fn status(&self) -> Option<String> {
+        self.duckdb_conn.as_ref().err().cloned()
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>, String> {
+        match &self.duckdb_conn {
+            Ok(conn) => conn.lock().map_err(|e| e.to_string()),
+            Err(e) => Err(format!("Chat history is unavailable: {}", e)),
+        }
+    }
+
+    pub fn insert_message(&self, message: &NewMessage) -> Result<(), String> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO chat_sessions (session_id, updated_at, active_leaf_id)
+             VALUES (?, CURRENT_TIMESTAMP, ?)
+             ON CONFLICT (session_id) DO UPDATE
+             SET updated_at = EXCLUDED.updated_at, active_leaf_id = EXCLUDED.active_leaf_id",
+            [&message.session_id, &message.id],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO messages (id, session_id, role, content, parent_id, model, prompt_tokens, completion_tokens, latency_ms)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            duckdb::params![
+                message.id,
+                message.session_id,
+                message.role,
+                message.content,
+                message.parent_id,
+                message.model,
+                message.prompt_tokens,
+                message.completion_tokens,
+                message.latency_ms,
+            ],
+        )
+        .and_then(|_| reindex_messages(&conn, "id = ?", &message.id))
+        .map_err(|e| e.to_string())
+    }
+
+    /// Stores `response` as an assistant reply. When `message_id` is an
+    /// assistant message it is updated in place; otherwise the newest reply to
+    /// `message_id` is updated, or a new reply is added if there is none.
+    pub fn upsert_response(
         &self,
-        id: &str,
-        session_id: &str,
-        message: &str,
+        message_id: &str,
         response: &str,
+        meta: &ResponseMeta,
     ) -> Result<(), String> {
-        let conn = self.duckdb_conn.lock().map_err(|e| e.to_string())?;
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT INTO messages (id, session_id, message, response) VALUES (?, ?, ?, ?)",
-            &[id, session_id, message, response],
+            "UPDATE chat_sessions SET updated_at = CURRENT_TIMESTAMP
+             WHERE session_id = (SELECT session_id FROM messages WHERE id = ?)",
+            [message_id],
         )
-        .map(|_| ())
-        .map_err(|e| e.to_string())
-    }
+        .map_err(|e| e.to_string())?;
 
-    pub fn update_message_response(&self, id: &str, response: &str) -> Result<(), String> {
-        let conn = self.duckdb_conn.lock().map_err(|e| e.to_string())?;
+        let existing = conn.query_row(
+            "SELECT id FROM messages
+             WHERE role = 'assistant' AND (id = ? OR parent_id = ?)
+             ORDER BY id = ? DESC, created_at DESC, rowid DESC
+             LIMIT 1",
+            [message_id, message_id, message_id],
+            |row| row.get::<_, String>(0),
+        );
+        match existing {
+            Ok(id) => {
+                return conn
+                    .execute(
+                        "UPDATE messages
+                         SET content = ?,
+                             model = COALESCE(?, model),
+                             prompt_tokens = COALESCE(?, prompt_tokens),
+                             completion_tokens = COALESCE(?, completion_tokens),
+                             latency_ms = COALESCE(?, latency_ms)
+                         WHERE id = ?",
+                        duckdb::params![
+                            response,
+                            meta.model,
+                            meta.prompt_tokens,
+                            meta.completion_tokens,
+                            meta.latency_ms,
+                            id,
+                        ],
+                    )
+                    .and_then(|_| reindex_messages(&conn, "id = ?", &id))
+                    .map_err(|e| e.to_string());
+            }
+            Err(duckdb::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let inserted = conn
+            .execute(
+                "INSERT INTO messages (id, session_id, role, content, parent_id, model, prompt_tokens, completion_tokens, latency_ms)
+                 SELECT ?, session_id, 'assistant', ?, id, ?, ?, ?, ? FROM messages WHERE id = ?",
+                duckdb::params![
+                    id,
+                    response,
+                    meta.model,
+                    meta.prompt_tokens,
+                    meta.completion_tokens,
+                    meta.latency_ms,
+                    message_id,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        if inserted == 0 {
+            return Err(format!("Message {} not found", message_id));
+        }
         conn.execute(
-            "UPDATE messages SET response = ? WHERE id = ?",
-            &[response, id],
+            "UPDATE chat_sessions SET active_leaf_id = ?
+             WHERE session_id = (SELECT session_id FROM messages WHERE id = ?)",
+            [&id, &id],
         )
+        .and_then(|_| reindex_messages(&conn, "id = ?", &id))
         .map(|_| ())
         .map_err(|e| e.to_string())
     }
 
+    pub fn get_message(&self, message_id: &str) -> Result<Message, String> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM messages WHERE id = ?", MESSAGE_COLUMNS),
+            [message_id],
+            message_from_row,
+        )
+        .map_err(|e| match e {
+            duckdb::Error::QueryReturnedNoRows => format!("Message {} not found", message_id),
+            e => e.to_string(),
+        })
+    }
+
     pub fn get_chat_history(&self, session_id: &str) -> Result<Vec<Message>, String> {
-        let conn = self.duckdb_conn.lock().map_err(|e| e.to_string())?;
+        let conn = self.conn()?;
         let mut stmt = conn
-            .prepare("SELECT id, session_id, message, response, CAST(timestamp AS VARCHAR) AS timestamp FROM messages WHERE session_id = ? ORDER BY timestamp")
+            .prepare(&format!(
+                "SELECT {} FROM messages WHERE session_id = ? ORDER BY created_at, rowid",
+                MESSAGE_COLUMNS
+            ))
             .map_err(|e| e.to_string())?;
         let rows = stmt
-            .query_map([session_id], |row| {
-                Ok(Message {
-                    id: row.get(0)?,         // VARCHAR -> String
-                    session_id: row.get(1)?, // VARCHAR -> String
-                    message: row.get(2)?,    // VARCHAR -> String
-                    response: row.get(3)?,   // VARCHAR -> String
-                    timestamp: row.get(4)?,  // Now CAST to VARCHAR -> String
-                })
-            })
+            .query_map([session_id], message_from_row)
             .map_err(|e| e.to_string())?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| e.to_string())?;
 
-        // Log the entire result
-        println!("Chat history for session {}: {:?}", session_id, rows);
+        println!("Loaded {} messages for session {}", rows.len(), session_id);
+
+        Ok(rows)
+    }
 
-        // Log individual messages
-        for msg in &rows {
-            println!(
-                "Message ID: {}, Session: {}, Message: {}, Response: {}, Timestamp: {}",
-                msg.id, msg.session_id, msg.message, msg.response, msg.timestamp
-            );
+    /// Returns the conversation the session currently shows: the path from
+    /// the root to its active leaf, with each message's position among its
+    /// siblings.
+    pub fn get_active_branch(&self, session_id: &str) -> Result<Vec<BranchMessage>, String> {
+        let active_leaf = {
+            let conn = self.conn()?;
+            conn.query_row(
+                "SELECT active_leaf_id FROM chat_sessions WHERE session_id = ?",
+                [session_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .or_else(|e| match e {
+                duckdb::Error::QueryReturnedNoRows => Ok(None),
+                e => Err(e.to_string()),
+            })?
+        };
+        let messages = self.get_chat_history(session_id)?;
+        Ok(branch_path(&messages, active_leaf.as_deref()))
+    }
+
+    /// Makes the branch through `message_id` the visible one, following the
+    /// newest reply at each step below it.
+    pub fn switch_branch(&self, message_id: &str) -> Result<String, String> {
+        let message = self.get_message(message_id)?;
+        let messages = self.get_chat_history(&message.session_id)?;
+
+        let mut leaf = message.id;
+        while let Some(child) = messages
+            .iter()
+            .rev()
+            .find(|m| m.parent_id.as_deref() == Some(leaf.as_str()))
+        {
+            leaf = child.id.clone();
         }
 
-        Ok(rows)
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE chat_sessions SET active_leaf_id = ? WHERE session_id = ?",
+            [&leaf, &message.session_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(message.session_id)
+    }
+
+    /// Returns the stored summary of older turns and the id of the last
+    /// message it covers.
+    pub fn get_session_summary(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<(String, String)>, String> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "SELECT summary, summary_through FROM chat_sessions WHERE session_id = ?",
+            [session_id],
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                ))
+            },
+        )
+        .map(|(summary, through)| summary.zip(through))
+        .or_else(|e| match e {
+            duckdb::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e.to_string()),
+        })
+    }
+
+    pub fn set_session_summary(
+        &self,
+        session_id: &str,
+        summary: &str,
+        through: &str,
+    ) -> Result<(), String> {
+        let conn = self.conn()?;
+        conn.execute(
+            "UPDATE chat_sessions SET summary = ?, summary_through = ? WHERE session_id = ?",
+            [summary, through, session_id],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
     }
 
     pub fn update_session_title(&self, session_id: &str, title: &str) -> Result<(), String> {
-        let conn = self.duckdb_conn.lock().map_err(|e| e.to_string())?;
+        let conn = self.conn()?;
         conn.execute(
-            "INSERT INTO chat_sessions (session_id, title) 
-             VALUES (?, ?) 
+            "INSERT INTO chat_sessions (session_id, title, updated_at) 
+             VALUES (?, ?, CURRENT_TIMESTAMP) 
              ON CONFLICT (session_id) DO UPDATE SET title = EXCLUDED.title",
             &[session_id, title],
         )
@@ -118,18 +549,685 @@ impl Database {
         .map_err(|e| e.to_string())
     }
 
-    pub fn get_session_info(&self) -> Result<Vec<(String, String)>, String> {
-        let conn = self.duckdb_conn.lock().map_err(|e| e.to_string())?;
+    pub fn create_session(&self, session_id: &str, title: Option<&str>) -> Result<(), String> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO chat_sessions (session_id, title, updated_at) VALUES (?, ?, CURRENT_TIMESTAMP)",
+            duckdb::params![session_id, title],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    /// Removes a session together with all of its messages.
+    pub fn delete_session(&self, session_id: &str) -> Result<(), String> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "DELETE FROM message_terms
+             WHERE message_id IN (SELECT id FROM messages WHERE session_id = ?)",
+            [session_id],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM messages WHERE session_id = ?", [session_id])
+            .map_err(|e| e.to_string())?;
+        let deleted = tx
+            .execute(
+                "DELETE FROM chat_sessions WHERE session_id = ?",
+                [session_id],
+            )
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+
+        if deleted == 0 {
+            return Err(format!("Session {} not found", session_id));
+        }
+        Ok(())
+    }
+
+    pub fn set_session_flag(
+        &self,
+        session_id: &str,
+        flag: SessionFlag,
+        value: bool,
+    ) -> Result<(), String> {
+        let column = match flag {
+            SessionFlag::Pinned => "pinned",
+            SessionFlag::Archived => "archived",
+        };
+        let conn = self.conn()?;
+        let updated = conn
+            .execute(
+                &format!(
+                    "UPDATE chat_sessions SET {} = ? WHERE session_id = ?",
+                    column
+                ),
+                duckdb::params![value, session_id],
+            )
+            .map_err(|e| e.to_string())?;
+
+        if updated == 0 {
+            return Err(format!("Session {} not found", session_id));
+        }
+        Ok(())
+    }
+
+    /// Lists sessions with pinned ones first, then by most recent activity.
+    pub fn get_session_info(&self, include_archived: bool) -> Result<Vec<ChatSession>, String> {
+        let conn = self.conn()?;
         let mut stmt = conn
-            .prepare("SELECT session_id, title FROM chat_sessions ORDER BY created_at DESC")
+            .prepare(
+                "SELECT
+                    s.session_id,
+                    s.title,
+                    CAST(s.created_at AS VARCHAR),
+                    CAST(COALESCE(s.updated_at, s.created_at) AS VARCHAR),
+                    COALESCE(m.message_count, 0),
+                    LEFT(m.last_message, 120),
+                    COALESCE(s.pinned, false),
+                    COALESCE(s.archived, false)
+                 FROM chat_sessions s
+                 LEFT JOIN (
+                    SELECT
+                        session_id,
+                        COUNT(*) AS message_count,
+                        arg_max(content, created_at) FILTER (WHERE content <> '') AS last_message
+                    FROM messages
+                    GROUP BY session_id
+                 ) m ON m.session_id = s.session_id
+                 WHERE ? OR NOT COALESCE(s.archived, false)
+                 ORDER BY COALESCE(s.pinned, false) DESC, COALESCE(s.updated_at, s.created_at) DESC",
+            )
             .map_err(|e| e.to_string())?;
 
         let rows = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .query_map([include_archived], |row| {
+                Ok(ChatSession {
+                    id: row.get(0)?,
+                    title: title_or_untitled(row.get(1)?),
+                    created_at: row.get(2)?,
+                    updated_at: row.get(3)?,
+                    message_count: row.get(4)?,
+                    last_message: row.get(5)?,
+                    pinned: row.get(6)?,
+                    archived: row.get(7)?,
+                })
+            })
             .map_err(|e| e.to_string())?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| e.to_string())?;
 
         Ok(rows)
     }
+
+    /// Ranks messages containing every term of `query` using BM25 over the
+    /// `message_terms` index.
+    pub fn search_messages(
+        &self,
+        query: &str,
+        session_id: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "WITH query_terms AS (
+                    SELECT DISTINCT term
+                    FROM (SELECT unnest(regexp_split_to_array(lower($1), '{}')) AS term)
+                    WHERE term <> ''
+                 ),
+                 doc_lengths AS (
+                    SELECT message_id, SUM(tf) AS len FROM message_terms GROUP BY message_id
+                 ),
+                 corpus AS (
+                    SELECT COUNT(*) AS n, AVG(len) AS avg_len FROM doc_lengths
+                 ),
+                 doc_freq AS (
+                    SELECT term, COUNT(*) AS df
+                    FROM message_terms
+                    WHERE term IN (SELECT term FROM query_terms)
+                    GROUP BY term
+                 ),
+                 scores AS (
+                    SELECT
+                        t.message_id,
+                        SUM(
+                            ln(1 + (c.n - f.df + 0.5) / (f.df + 0.5))
+                            * t.tf * 2.2
+                            / (t.tf + 1.2 * (0.25 + 0.75 * d.len / c.avg_len))
+                        ) AS score,
+                        COUNT(*) AS matched
+                    FROM message_terms t
+                    JOIN doc_freq f ON f.term = t.term
+                    JOIN doc_lengths d ON d.message_id = t.message_id
+                    CROSS JOIN corpus c
+                    GROUP BY t.message_id
+                 )
+                 SELECT
+                    m.id,
+                    m.session_id,
+                    s.title,
+                    m.role,
+                    m.content,
+                    CAST(m.created_at AS VARCHAR),
+                    sc.score
+                 FROM scores sc
+                 JOIN messages m ON m.id = sc.message_id
+                 LEFT JOIN chat_sessions s ON s.session_id = m.session_id
+                 WHERE sc.matched = (SELECT COUNT(*) FROM query_terms)
+                    AND (CAST($2 AS VARCHAR) IS NULL OR m.session_id = $2)
+                    AND (CAST($3 AS TIMESTAMP) IS NULL OR m.created_at >= CAST($3 AS TIMESTAMP))
+                    AND (CAST($4 AS TIMESTAMP) IS NULL OR m.created_at <= CAST($4 AS TIMESTAMP))
+                 ORDER BY sc.score DESC
+                 LIMIT $5",
+                TERM_SEPARATOR
+            ))
+            .map_err(|e| e.to_string())?;
+
+        let rows = stmt
+            .query_map(duckdb::params![query, session_id, from, to, limit], |row| {
+                Ok(SearchHit {
+                    message_id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    session_title: title_or_untitled(row.get(2)?),
+                    role: row.get(3)?,
+                    content: row.get(4)?,
+                    created_at: row.get(5)?,
+                    score: row.get(6)?,
+                })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(rows)
+    }
+
+    pub fn export_session(&self, session_id: &str) -> Result<SessionExport, String> {
+        let mut export = {
+            let conn = self.conn()?;
+            conn.query_row(
+                "SELECT session_id, title, CAST(created_at AS VARCHAR), CAST(updated_at AS VARCHAR),
+                    COALESCE(pinned, false), COALESCE(archived, false)
+                 FROM chat_sessions WHERE session_id = ?",
+                [session_id],
+                |row| {
+                    Ok(SessionExport {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        created_at: row.get(2)?,
+                        updated_at: row.get(3)?,
+                        pinned: row.get(4)?,
+                        archived: row.get(5)?,
+                        messages: Vec::new(),
+                    })
+                },
+            )
+            .map_err(|e| match e {
+                duckdb::Error::QueryReturnedNoRows => format!("Session {} not found", session_id),
+                e => e.to_string(),
+            })?
+        };
+        export.messages = self.get_chat_history(session_id)?;
+        Ok(export)
+    }
+
+    /// Writes an exported session back into the database. Message ids that
+    /// are already taken get fresh ids, with parent links updated to match.
+    pub fn import_session(
+        &self,
+        session: &SessionExport,
+        conflict: ImportConflict,
+    ) -> Result<ImportOutcome, String> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+        let exists: i64 = tx
+            .query_row(
+                "SELECT COUNT(*) FROM chat_sessions WHERE session_id = ?",
+                [&session.id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let (session_id, outcome) = match (exists > 0, conflict) {
+            (false, _) => (session.id.clone(), ImportOutcome::Imported),
+            (true, ImportConflict::Skip) => return Ok(ImportOutcome::Skipped),
+            (true, ImportConflict::Replace) => {
+                tx.execute(
+                    "DELETE FROM message_terms
+                     WHERE message_id IN (SELECT id FROM messages WHERE session_id = ?)",
+                    [&session.id],
+                )
+                .and_then(|_| {
+                    tx.execute("DELETE FROM messages WHERE session_id = ?", [&session.id])
+                })
+                .and_then(|_| {
+                    tx.execute(
+                        "DELETE FROM chat_sessions WHERE session_id = ?",
+                        [&session.id],
+                    )
+                })
+                .map_err(|e| e.to_string())?;
+                (session.id.clone(), ImportOutcome::Replaced)
+            }
+            (true, ImportConflict::Duplicate) => {
+                (uuid::Uuid::new_v4().to_string(), ImportOutcome::Duplicated)
+            }
+        };
+
+        tx.execute(
+            "INSERT INTO chat_sessions (session_id, title, created_at, updated_at, pinned, archived)
+             VALUES (?, ?, CAST(? AS TIMESTAMP), CAST(? AS TIMESTAMP), ?, ?)",
+            duckdb::params![
+                session_id,
+                session.title,
+                session.created_at,
+                session.updated_at.as_ref().unwrap_or(&session.created_at),
+                session.pinned,
+                session.archived,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut ids = HashMap::new();
+        for message in &session.messages {
+            let taken: i64 = tx
+                .query_row(
+                    "SELECT COUNT(*) FROM messages WHERE id = ?",
+                    [&message.id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            let id = if taken > 0 {
+                uuid::Uuid::new_v4().to_string()
+            } else {
+                message.id.clone()
+            };
+            ids.insert(message.id.clone(), id);
+        }
+
+        for message in &session.messages {
+            let parent_id = message
+                .parent_id
+                .as_ref()
+                .map(|parent| ids.get(parent).unwrap_or(parent));
+            tx.execute(
+                "INSERT INTO messages (id, session_id, role, content, parent_id, model, prompt_tokens, completion_tokens, latency_ms, created_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, CAST(? AS TIMESTAMP))",
+                duckdb::params![
+                    ids[&message.id],
+                    session_id,
+                    message.role,
+                    message.content,
+                    parent_id,
+                    message.model,
+                    message.prompt_tokens,
+                    message.completion_tokens,
+                    message.latency_ms,
+                    message.created_at,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+
+        reindex_messages(&tx, "session_id = ?", &session_id).map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(outcome)
+    }
+
+    pub fn chat_stats(&self, from: Option<&str>, to: Option<&str>) -> Result<ChatStats, String> {
+        let conn = self.conn()?;
+        let query = |sql: &str| conn.prepare(&format!("{} {}", STATS_SCOPE, sql));
+
+        let (total_messages, total_responses, avg_response_length) = query(
+            "SELECT
+                COUNT(*),
+                COUNT(*) FILTER (WHERE role = 'assistant'),
+                AVG(length(content)) FILTER (WHERE role = 'assistant' AND content <> '')
+             FROM scoped",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_row(duckdb::params![from, to], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+        let messages_per_day = query(
+            "SELECT
+                CAST(CAST(created_at AS DATE) AS VARCHAR) AS day,
+                COUNT(*) FILTER (WHERE role = 'user'),
+                COUNT(*) FILTER (WHERE role = 'assistant')
+             FROM scoped
+             GROUP BY day
+             ORDER BY day",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(duckdb::params![from, to], |row| {
+                Ok(DailyActivity {
+                    day: row.get(0)?,
+                    user_messages: row.get(1)?,
+                    assistant_messages: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+        let models = query(
+            "SELECT
+                COALESCE(model, 'unknown') AS model,
+                COUNT(*) AS responses,
+                AVG(latency_ms),
+                AVG(length(content)) FILTER (WHERE content <> ''),
+                CAST(COALESCE(SUM(prompt_tokens), 0) AS BIGINT),
+                CAST(COALESCE(SUM(completion_tokens), 0) AS BIGINT),
+                AVG(completion_tokens * 1000.0 / latency_ms) FILTER (WHERE latency_ms > 0)
+             FROM scoped
+             WHERE role = 'assistant'
+             GROUP BY 1
+             ORDER BY responses DESC",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(duckdb::params![from, to], |row| {
+                Ok(ModelUsage {
+                    model: row.get(0)?,
+                    responses: row.get(1)?,
+                    avg_latency_ms: row.get(2)?,
+                    avg_response_length: row.get(3)?,
+                    prompt_tokens: row.get(4)?,
+                    completion_tokens: row.get(5)?,
+                    tokens_per_second: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+        let top_sessions = query(
+            "SELECT m.session_id, s.title, COUNT(*) AS message_count
+             FROM scoped m
+             LEFT JOIN chat_sessions s ON s.session_id = m.session_id
+             GROUP BY 1, 2
+             ORDER BY message_count DESC
+             LIMIT $3",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(duckdb::params![from, to, TOP_SESSIONS_LIMIT], |row| {
+                Ok(SessionActivity {
+                    session_id: row.get(0)?,
+                    title: title_or_untitled(row.get(1)?),
+                    message_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+        let busiest_hours = query(
+            "SELECT CAST(hour(created_at) AS BIGINT) AS hour, COUNT(*) AS messages
+             FROM scoped
+             GROUP BY hour
+             ORDER BY messages DESC, hour",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(duckdb::params![from, to], |row| {
+                Ok(HourlyActivity {
+                    hour: row.get(0)?,
+                    messages: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+
+        Ok(ChatStats {
+            total_messages,
+            total_responses,
+            avg_response_length,
+            messages_per_day,
+            models,
+            top_sessions,
+            busiest_hours,
+        })
+    }
+
+    pub fn list_presets(&self) -> Result<Vec<PromptPreset>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM prompt_presets ORDER BY lower(name)",
+                PRESET_COLUMNS
+            ))
+            .map_err(|e| e.to_string())?;
+        let presets = stmt
+            .query_map([], preset_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(presets)
+    }
+
+    pub fn get_preset(&self, preset_id: &str) -> Result<PromptPreset, String> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!("SELECT {} FROM prompt_presets WHERE id = ?", PRESET_COLUMNS),
+            [preset_id],
+            preset_from_row,
+        )
+        .map_err(|e| match e {
+            duckdb::Error::QueryReturnedNoRows => format!("Preset {} not found", preset_id),
+            e => e.to_string(),
+        })
+    }
+
+    /// Creates the preset, or updates it when `preset_id` already exists.
+    /// Preset names are unique, ignoring case.
+    pub fn save_preset(&self, preset_id: &str, name: &str, content: &str) -> Result<(), String> {
+        let conn = self.conn()?;
+        let taken: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM prompt_presets WHERE lower(name) = lower(?) AND id <> ?",
+                [name, preset_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if taken > 0 {
+            return Err(format!("A preset named \"{}\" already exists", name));
+        }
+
+        let updated = conn
+            .execute(
+                "UPDATE prompt_presets SET name = ?, content = ?, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?",
+                [name, content, preset_id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            conn.execute(
+                "INSERT INTO prompt_presets (id, name, content) VALUES (?, ?, ?)",
+                [preset_id, name, content],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a preset and detaches it from any sessions using it.
+    pub fn delete_preset(&self, preset_id: &str) -> Result<(), String> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE chat_sessions SET preset_id = NULL WHERE preset_id = ?",
+            [preset_id],
+        )
+        .map_err(|e| e.to_string())?;
+        let deleted = tx
+            .execute("DELETE FROM prompt_presets WHERE id = ?", [preset_id])
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+
+        if deleted == 0 {
+            return Err(format!("Preset {} not found", preset_id));
+        }
+        Ok(())
+    }
+
+    pub fn set_session_preset(
+        &self,
+        session_id: &str,
+        preset_id: Option<&str>,
+    ) -> Result<(), String> {
+        let conn = self.conn()?;
+        conn.execute(
+            "INSERT INTO chat_sessions (session_id, preset_id, updated_at)
+             VALUES (?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT (session_id) DO UPDATE SET preset_id = EXCLUDED.preset_id",
+            duckdb::params![session_id, preset_id],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    pub fn get_session_preset(&self, session_id: &str) -> Result<Option<PromptPreset>, String> {
+        let conn = self.conn()?;
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM prompt_presets
+                 WHERE id = (SELECT preset_id FROM chat_sessions WHERE session_id = ?)",
+                PRESET_COLUMNS
+            ),
+            [session_id],
+            preset_from_row,
+        )
+        .map(Some)
+        .or_else(|e| match e {
+            duckdb::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Database {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn, std::path::Path::new(":memory:")).unwrap();
+        Database {
+            duckdb_conn: Ok(Arc::new(Mutex::new(conn))),
+        }
+    }
+
+    fn add(db: &Database, id: &str, session_id: &str, content: &str) {
+        db.insert_message(&NewMessage {
+            id: id.to_string(),
+            session_id: session_id.to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+    }
+
+    fn message(id: &str, parent_id: Option<&str>) -> Message {
+        Message {
+            id: id.to_string(),
+            session_id: "s1".to_string(),
+            role: "user".to_string(),
+            content: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            model: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            latency_ms: None,
+            created_at: String::new(),
+        }
+    }
+
+    fn path_ids(path: &[BranchMessage]) -> Vec<&str> {
+        path.iter().map(|m| m.message.id.as_str()).collect()
+    }
+
+    // u1 -> a1 -> u2, with a1b as a regenerated alternative to a1
+    fn tree() -> Vec<Message> {
+        vec![
+            message("u1", None),
+            message("a1", Some("u1")),
+            message("u2", Some("a1")),
+            message("a1b", Some("u1")),
+        ]
+    }
+
+    #[test]
+    fn branch_path_follows_parents_from_the_leaf() {
+        let messages = tree();
+        let path = branch_path(&messages, Some("u2"));
+        assert_eq!(path_ids(&path), vec!["u1", "a1", "u2"]);
+        assert_eq!((path[1].sibling_index, path[1].sibling_count), (0, 2));
+        assert_eq!((path[2].sibling_index, path[2].sibling_count), (0, 1));
+
+        let path = branch_path(&messages, Some("a1b"));
+        assert_eq!(path_ids(&path), vec!["u1", "a1b"]);
+        assert_eq!((path[1].sibling_index, path[1].sibling_count), (1, 2));
+    }
+
+    #[test]
+    fn branch_path_falls_back_to_the_newest_message() {
+        let messages = tree();
+        assert_eq!(path_ids(&branch_path(&messages, None)), vec!["u1", "a1b"]);
+        assert_eq!(
+            path_ids(&branch_path(&messages, Some("missing"))),
+            vec!["u1", "a1b"]
+        );
+        assert!(branch_path(&[], None).is_empty());
+    }
+
+    #[test]
+    fn branch_path_stops_on_a_parent_cycle() {
+        let messages = vec![message("x", Some("y")), message("y", Some("x"))];
+        assert!(branch_path(&messages, Some("x")).len() <= messages.len() + 1);
+    }
+
+    #[test]
+    fn search_ranks_denser_matches_first() {
+        let db = test_db();
+        add(
+            &db,
+            "long",
+            "s1",
+            "rust is mentioned once in this rather long message about other things",
+        );
+        add(&db, "dense", "s1", "rust rust rust");
+        add(&db, "none", "s1", "python only");
+
+        let hits = db.search_messages("Rust", None, None, None, 10).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|h| h.message_id.as_str()).collect();
+        assert_eq!(ids, vec!["dense", "long"]);
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    #[test]
+    fn search_requires_every_term_and_respects_session_filter() {
+        let db = test_db();
+        add(&db, "both", "s1", "async rust runtime");
+        add(&db, "one", "s1", "async python");
+        add(&db, "other", "s2", "async rust in another session");
+
+        let hits = db
+            .search_messages("rust async", None, None, None, 10)
+            .unwrap();
+        let mut ids: Vec<&str> = hits.iter().map(|h| h.message_id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["both", "other"]);
+
+        let hits = db
+            .search_messages("rust async", Some("s1"), None, None, 10)
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_id, "both");
+    }
 }
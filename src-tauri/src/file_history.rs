@@ -0,0 +1,380 @@
+use crate::file_jobs::Progress;
+use crate::file_ops::path_exists;
+use crate::transfer::move_path;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+
+// Operations kept for undo; the trash of older ones is freed
+const MAX_HISTORY: usize = 100;
+// Trash left behind by earlier runs is emptied after this long
+const TRASH_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A completed file operation, with what's needed to reverse it. Deleted
+/// files and undone copies are parked under the app's trash directory.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileOp {
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        source: PathBuf,
+        destination: PathBuf,
+        /// Where the copy is kept while the operation is undone
+        trashed: PathBuf,
+    },
+    Remove {
+        path: PathBuf,
+        trashed: PathBuf,
+    },
+    CreateDir {
+        path: PathBuf,
+        /// The outermost directory that didn't exist before
+        created: PathBuf,
+    },
+    /// Several operations that are undone and redone together
+    Batch {
+        ops: Vec<FileOp>,
+    },
+}
+
+fn ensure_free(path: &Path) -> Result<(), String> {
+    if path_exists(path) {
+        return Err(format!("{} already exists", path.display()));
+    }
+    Ok(())
+}
+
+fn relocate_with(from: &Path, to: &Path, progress: &Progress) -> Result<(), String> {
+    if !path_exists(from) {
+        return Err(format!("{} no longer exists", from.display()));
+    }
+    ensure_free(to)?;
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    move_path(from, to, progress).map_err(|e| format!("Failed to move {}: {}", from.display(), e))
+}
+
+fn relocate(from: &Path, to: &Path) -> Result<(), String> {
+    relocate_with(from, to, &Progress::silent())
+}
+
+impl FileOp {
+    /// Performs the operation again after it was undone.
+    fn apply(&self) -> Result<(), String> {
+        match self {
+            FileOp::Rename { from, to } | FileOp::Move { from, to } => relocate(from, to),
+            FileOp::Copy {
+                destination,
+                trashed,
+                ..
+            } => relocate(trashed, destination),
+            FileOp::Remove { path, trashed } => relocate(path, trashed),
+            FileOp::CreateDir { path, .. } => {
+                fs::create_dir_all(path).map_err(|e| format!("Failed to create folder: {}", e))
+            }
+            FileOp::Batch { ops } => {
+                for (done, op) in ops.iter().enumerate() {
+                    if let Err(e) = op.apply() {
+                        for op in ops[..done].iter().rev() {
+                            let _ = op.revert();
+                        }
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn revert(&self) -> Result<(), String> {
+        match self {
+            FileOp::Rename { from, to } | FileOp::Move { from, to } => relocate(to, from),
+            FileOp::Copy {
+                destination,
+                trashed,
+                ..
+            } => relocate(destination, trashed),
+            FileOp::Remove { path, trashed } => relocate(trashed, path),
+            FileOp::CreateDir { path, created } => {
+                // Only the directories this operation made are removed, and
+                // only while they're still empty
+                for dir in path.ancestors() {
+                    fs::remove_dir(dir)
+                        .map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))?;
+                    if dir == created {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+            FileOp::Batch { ops } => {
+                for (done, op) in ops.iter().rev().enumerate() {
+                    if let Err(e) = op.revert() {
+                        for op in &ops[ops.len() - done..] {
+                            let _ = op.apply();
+                        }
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Frees the trash entry of an operation that can no longer be undone.
+    fn discard(&self) {
+        match self {
+            FileOp::Copy { trashed, .. } | FileOp::Remove { trashed, .. } => {
+                if let Some(entry) = trashed.parent() {
+                    let _ = fs::remove_dir_all(entry);
+                }
+            }
+            FileOp::Batch { ops } => ops.iter().for_each(FileOp::discard),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Journal {
+    undo: Vec<FileOp>,
+    redo: Vec<FileOp>,
+}
+
+pub struct FileHistory {
+    trash_dir: PathBuf,
+    journal: Mutex<Journal>,
+}
+
+impl FileHistory {
+    pub fn new(trash_dir: PathBuf) -> Self {
+        empty_old_trash(&trash_dir);
+        FileHistory {
+            trash_dir,
+            journal: Mutex::new(Journal::default()),
+        }
+    }
+
+    /// A fresh location in the trash for `path`, keeping its file name.
+    pub fn trash_path(&self, path: &Path) -> PathBuf {
+        self.trash_dir
+            .join(uuid::Uuid::new_v4().to_string())
+            .join(path.file_name().unwrap_or_default())
+    }
+
+    /// Moves `path` into the trash, returning the operation that restores
+    /// it without recording it.
+    pub fn stash(&self, path: &Path, progress: &Progress) -> Result<FileOp, String> {
+        let trashed = self.trash_path(path);
+        relocate_with(path, &trashed, progress)?;
+        Ok(FileOp::Remove {
+            path: path.to_path_buf(),
+            trashed,
+        })
+    }
+
+    /// Moves `path` into the trash and records the deletion.
+    pub fn trash(&self, path: &Path, progress: &Progress) -> Result<(), String> {
+        let op = self.stash(path, progress)?;
+        self.record(op);
+        Ok(())
+    }
+
+    /// Adds a completed operation to the journal. Anything that was undone
+    /// can no longer be redone afterwards.
+    pub fn record(&self, op: FileOp) {
+        let Ok(mut journal) = self.journal.lock() else {
+            return;
+        };
+        for op in journal.redo.drain(..) {
+            op.discard();
+        }
+        journal.undo.push(op);
+        if journal.undo.len() > MAX_HISTORY {
+            journal.undo.remove(0).discard();
+        }
+    }
+
+    /// Records the parts of one user action as a single operation.
+    pub fn record_all(&self, mut ops: Vec<FileOp>) {
+        match ops.len() {
+            0 => {}
+            1 => self.record(ops.remove(0)),
+            _ => self.record(FileOp::Batch { ops }),
+        }
+    }
+
+    fn undo(&self) -> Result<FileOp, String> {
+        let mut journal = self.journal.lock().map_err(|e| e.to_string())?;
+        let op = journal
+            .undo
+            .pop()
+            .ok_or_else(|| "Nothing to undo".to_string())?;
+        if let Err(e) = op.revert() {
+            journal.undo.push(op);
+            return Err(e);
+        }
+        journal.redo.push(op.clone());
+        Ok(op)
+    }
+
+    fn redo(&self) -> Result<FileOp, String> {
+        let mut journal = self.journal.lock().map_err(|e| e.to_string())?;
+        let op = journal
+            .redo
+            .pop()
+            .ok_or_else(|| "Nothing to redo".to_string())?;
+        if let Err(e) = op.apply() {
+            journal.redo.push(op);
+            return Err(e);
+        }
+        journal.undo.push(op.clone());
+        Ok(op)
+    }
+}
+
+fn empty_old_trash(trash_dir: &Path) {
+    let Ok(entries) = fs::read_dir(trash_dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > TRASH_RETENTION);
+        if expired {
+            if let Err(e) = fs::remove_dir_all(entry.path()) {
+                println!(
+                    "[empty_old_trash] Failed to remove {}: {}",
+                    entry.path().display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Reverses the most recent file operation and returns it. Restoring from
+/// the trash can mean copying a large folder across devices, so the work
+/// runs on a blocking thread.
+#[tauri::command]
+pub async fn undo_last_file_op(app_handle: tauri::AppHandle) -> Result<FileOp, String> {
+    let op = tauri::async_runtime::spawn_blocking(move || app_handle.state::<FileHistory>().undo())
+        .await
+        .map_err(|e| format!("Undo failed: {}", e))??;
+    println!("[undo_last_file_op] Undid {:?}", op);
+    Ok(op)
+}
+
+/// Performs the most recently undone file operation again and returns it.
+#[tauri::command]
+pub async fn redo_file_op(app_handle: tauri::AppHandle) -> Result<FileOp, String> {
+    let op = tauri::async_runtime::spawn_blocking(move || app_handle.state::<FileHistory>().redo())
+        .await
+        .map_err(|e| format!("Redo failed: {}", e))??;
+    println!("[redo_file_op] Redid {:?}", op);
+    Ok(op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> (tempfile::TempDir, FileHistory) {
+        let dir = tempfile::tempdir().unwrap();
+        let history = FileHistory::new(dir.path().join("trash"));
+        (dir, history)
+    }
+
+    #[test]
+    fn undo_and_redo_a_rename() {
+        let (dir, history) = history();
+        let from = dir.path().join("a.txt");
+        let to = dir.path().join("b.txt");
+        fs::write(&from, "a").unwrap();
+        fs::rename(&from, &to).unwrap();
+        history.record(FileOp::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        });
+
+        history.undo().unwrap();
+        assert_eq!(fs::read_to_string(&from).unwrap(), "a");
+        assert!(!path_exists(&to));
+
+        history.redo().unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "a");
+        assert!(!path_exists(&from));
+    }
+
+    #[test]
+    fn undo_restores_a_trashed_folder() {
+        let (dir, history) = history();
+        let folder = dir.path().join("folder");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("file.txt"), "kept").unwrap();
+
+        history.trash(&folder, &Progress::silent()).unwrap();
+        assert!(!path_exists(&folder));
+
+        assert!(matches!(history.undo().unwrap(), FileOp::Remove { .. }));
+        assert_eq!(fs::read_to_string(folder.join("file.txt")).unwrap(), "kept");
+    }
+
+    #[test]
+    fn undo_parks_a_copy_in_the_trash() {
+        let (dir, history) = history();
+        let source = dir.path().join("source.txt");
+        let destination = dir.path().join("copy.txt");
+        fs::write(&source, "copied").unwrap();
+        fs::copy(&source, &destination).unwrap();
+        let trashed = history.trash_path(&destination);
+        history.record(FileOp::Copy {
+            source: source.clone(),
+            destination: destination.clone(),
+            trashed: trashed.clone(),
+        });
+
+        history.undo().unwrap();
+        assert!(!path_exists(&destination));
+        assert_eq!(fs::read_to_string(&trashed).unwrap(), "copied");
+        assert!(path_exists(&source));
+
+        history.redo().unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "copied");
+    }
+
+    #[test]
+    fn failed_undo_keeps_the_operation() {
+        let (dir, history) = history();
+        let from = dir.path().join("a.txt");
+        let to = dir.path().join("b.txt");
+        fs::write(&from, "new").unwrap();
+        fs::write(&to, "renamed").unwrap();
+        history.record(FileOp::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        });
+
+        assert!(history.undo().is_err());
+        assert_eq!(fs::read_to_string(&from).unwrap(), "new");
+        fs::remove_file(&from).unwrap();
+        history.undo().unwrap();
+        assert_eq!(fs::read_to_string(&from).unwrap(), "renamed");
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+
+// Minimum time between two progress events of a job
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct FileJobs {
+    /// Cancellation flags of the file operations currently running, by job id
+    jobs: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub operation: String,
+    pub items_done: u64,
+    pub items_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_path: Option<String>,
+}
+
+/// Tracks a running file operation, emitting `file_op_progress` events at
+/// most every `PROGRESS_INTERVAL`.
+pub struct Progress {
+    app_handle: Option<tauri::AppHandle>,
+    cancelled: Arc<AtomicBool>,
+    status: Mutex<(JobProgress, Instant)>,
+}
+
+impl Progress {
+    /// Progress that isn't reported and can't be cancelled, for moves made
+    /// on the app's behalf such as undo.
+    pub fn silent() -> Self {
+        Progress {
+            app_handle: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            status: Mutex::new((JobProgress::default(), Instant::now())),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails with `Interrupted` once the job was cancelled.
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled"));
+        }
+        Ok(())
+    }
+
+    fn update(&self, force: bool, change: impl FnOnce(&mut JobProgress)) {
+        let Ok(mut status) = self.status.lock() else {
+            return;
+        };
+        change(&mut status.0);
+        if let Some(app_handle) = &self.app_handle {
+            if force || status.1.elapsed() >= PROGRESS_INTERVAL {
+                status.1 = Instant::now();
+                let _ = app_handle.emit("file_op_progress", status.0.clone());
+            }
+        }
+    }
+
+    /// Counts the items and bytes under `path` as the job's total work.
+    pub fn measure(&self, path: &Path) {
+        let (items, bytes) = measure(path);
+        self.update(true, |progress| {
+            progress.items_total += items;
+            progress.bytes_total += bytes;
+        });
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.update(false, |progress| progress.bytes_done += bytes);
+    }
+
+    pub fn item_done(&self, path: &Path) {
+        self.update(false, |progress| {
+            progress.items_done += 1;
+            progress.current_path = Some(path.to_string_lossy().to_string());
+        });
+    }
+
+    /// Marks everything as done, for when the work finished without being
+    /// counted item by item, like a rename.
+    pub fn complete(&self) {
+        self.update(false, |progress| {
+            progress.items_done = progress.items_total;
+            progress.bytes_done = progress.bytes_total;
+        });
+    }
+
+    pub fn snapshot(&self) -> JobProgress {
+        self.status
+            .lock()
+            .map(|status| status.0.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Counts the items and bytes under `path`, without following symlinks.
+fn measure(path: &Path) -> (u64, u64) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return (0, 0);
+    };
+    if !metadata.is_dir() {
+        return (1, metadata.len());
+    }
+
+    let mut total = (1, 0);
+    for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+        let (items, bytes) = measure(&entry.path());
+        total.0 += items;
+        total.1 += bytes;
+    }
+    total
+}
+
+/// Runs `work` on a blocking thread as a job that `cancel_file_op` can stop.
+/// A last progress event is emitted when it ends.
+pub async fn run_job<T, F>(
+    app_handle: &tauri::AppHandle,
+    job_id: Option<String>,
+    operation: &str,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&tauri::AppHandle, &Progress) -> T + Send + 'static,
+{
+    let job_id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let jobs = app_handle.state::<FileJobs>();
+        let mut jobs = jobs.jobs.lock().map_err(|e| e.to_string())?;
+        if jobs.contains_key(&job_id) {
+            return Err(format!(
+                "A file operation with id {} is already running",
+                job_id
+            ));
+        }
+        jobs.insert(job_id.clone(), cancelled.clone());
+    }
+
+    let progress = Progress {
+        app_handle: Some(app_handle.clone()),
+        cancelled,
+        status: Mutex::new((
+            JobProgress {
+                job_id: job_id.clone(),
+                operation: operation.to_string(),
+                ..JobProgress::default()
+            },
+            Instant::now(),
+        )),
+    };
+    let handle = app_handle.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let result = work(&handle, &progress);
+        progress.update(true, |_| {});
+        result
+    })
+    .await
+    .map_err(|e| format!("File operation failed: {}", e));
+
+    let jobs = app_handle.state::<FileJobs>();
+    jobs.jobs.lock().map_err(|e| e.to_string())?.remove(&job_id);
+    result
+}
+
+#[tauri::command]
+pub fn cancel_file_op(app_handle: tauri::AppHandle, job_id: String) -> Result<(), String> {
+    let jobs = app_handle.state::<FileJobs>();
+    let jobs = jobs.jobs.lock().map_err(|e| e.to_string())?;
+    match jobs.get(&job_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            println!("[cancel_file_op] Cancelling job {}", job_id);
+            Ok(())
+        }
+        None => Err(format!("No file operation with id {}", job_id)),
+    }
+}
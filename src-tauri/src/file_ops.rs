@@ -1,8 +1,15 @@
+use crate::file_history::{FileHistory, FileOp};
+use crate::file_jobs::run_job;
+use crate::git::{self, GitFileStatus};
+use crate::path_guard::authorize;
+use crate::prompts::language_for_file;
+use crate::transfer::{ConflictMode, Transfer, TransferResult};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
-use tauri::command;
+use tauri::{command, Manager};
 
 #[derive(Debug, serde::Serialize)]
 pub struct FileOpResult {
@@ -34,144 +41,320 @@ pub struct FileMetadata {
     pub size: u64,
     pub modified: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    pub is_symlink: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
+    pub readonly: bool,
+    /// Unix permission bits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    pub hidden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Only filled in when git status was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_status: Option<GitFileStatus>,
+    /// Why the entry couldn't be read; the other fields are defaults then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<FileMetadata>>,
 }
 
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn is_hidden(name: &str, metadata: &fs::Metadata) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0 {
+            return true;
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = metadata;
+    name.starts_with('.')
+}
+
 impl FileMetadata {
     pub fn new(path: PathBuf) -> std::io::Result<Self> {
         match fs::symlink_metadata(&path) {
             Ok(metadata) => {
-                let modified = metadata
-                    .modified()
-                    .unwrap_or(SystemTime::UNIX_EPOCH)
-                    .duration_since(SystemTime::UNIX_EPOCH)
+                let name: String = path
+                    .file_name()
                     .unwrap_or_default()
-                    .as_secs();
+                    .to_string_lossy()
+                    .into();
+                let is_symlink = metadata.file_type().is_symlink();
+                let is_dir = metadata.is_dir();
+
+                #[cfg(unix)]
+                let mode = {
+                    use std::os::unix::fs::PermissionsExt;
+                    Some(metadata.permissions().mode() & 0o7777)
+                };
+                #[cfg(not(unix))]
+                let mode = None;
+
+                let (mime_type, language) = if is_dir {
+                    (None, None)
+                } else {
+                    (
+                        mime_guess::from_path(&path)
+                            .first()
+                            .map(|mime| mime.to_string()),
+                        language_for_file(&path.to_string_lossy()),
+                    )
+                };
 
                 Ok(FileMetadata {
-                    name: path
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .into(),
                     path: path.to_string_lossy().into(),
-                    is_dir: metadata.is_dir(),
+                    is_dir,
                     size: metadata.len(),
-                    modified,
+                    modified: unix_secs(metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)),
+                    created: metadata.created().ok().map(unix_secs),
+                    is_symlink,
+                    symlink_target: is_symlink
+                        .then(|| fs::read_link(&path).ok())
+                        .flatten()
+                        .map(|target| target.to_string_lossy().into()),
+                    readonly: metadata.permissions().readonly(),
+                    mode,
+                    hidden: is_hidden(&name, &metadata),
+                    mime_type,
+                    language,
+                    git_status: None,
+                    error: None,
                     children: None,
+                    name,
                 })
             }
             Err(e) => Err(e),
         }
     }
+
+    /// An entry that's listed but couldn't be read, such as a file that was
+    /// removed while listing or a folder without permission.
+    pub fn unreadable(path: PathBuf, error: impl ToString) -> Self {
+        FileMetadata {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            path: path.to_string_lossy().into(),
+            is_dir: false,
+            size: 0,
+            modified: 0,
+            created: None,
+            is_symlink: false,
+            symlink_target: None,
+            readonly: false,
+            mode: None,
+            hidden: false,
+            mime_type: None,
+            language: None,
+            git_status: None,
+            error: Some(error.to_string()),
+            children: None,
+        }
+    }
+
+    /// Metadata for `path`, or an entry carrying the error.
+    pub fn read(path: PathBuf) -> Self {
+        FileMetadata::new(path.clone()).unwrap_or_else(|e| FileMetadata::unreadable(path, e))
+    }
+}
+
+/// Like `Path::exists`, but also true for broken symlinks.
+pub(crate) fn path_exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
 }
 
 #[command]
-pub async fn rename(old_path: String, new_path: String) -> FileOpResult {
+pub async fn rename(
+    app_handle: tauri::AppHandle,
+    old_path: String,
+    new_path: String,
+) -> FileOpResult {
+    for path in [&old_path, &new_path] {
+        if let Err(e) = authorize(&app_handle, path, "rename").await {
+            return FileOpResult::error(&e);
+        }
+    }
+    // `fs::rename` replaces an existing file on Unix, and undo couldn't
+    // bring it back. A case-only rename of the same file is fine.
+    let same_file = old_path.to_lowercase() == new_path.to_lowercase()
+        && fs::canonicalize(&old_path).ok() == fs::canonicalize(&new_path).ok();
+    if path_exists(Path::new(&new_path)) && !same_file {
+        return FileOpResult::error(&format!("{} already exists", new_path));
+    }
     match fs::rename(&old_path, &new_path) {
-        Ok(_) => FileOpResult::success("Successfully renamed file/folder"),
+        Ok(_) => {
+            app_handle.state::<FileHistory>().record(FileOp::Rename {
+                from: PathBuf::from(old_path),
+                to: PathBuf::from(new_path),
+            });
+            FileOpResult::success("Successfully renamed file/folder")
+        }
         Err(e) => FileOpResult::error(&format!("Failed to rename: {}", e)),
     }
 }
 
+/// Moves a file or folder to the app's trash, from where
+/// `undo_last_file_op` can restore it. Runs as a job that reports
+/// `file_op_progress` events under `job_id` and can be cancelled.
 #[command]
-pub async fn remove(path: String) -> FileOpResult {
-    let path = PathBuf::from(path);
-    let result = if path.is_dir() {
-        fs::remove_dir_all(&path)
-    } else {
-        fs::remove_file(&path)
-    };
+pub async fn remove(
+    app_handle: tauri::AppHandle,
+    path: String,
+    job_id: Option<String>,
+) -> FileOpResult {
+    if let Err(e) = authorize(&app_handle, &path, "delete").await {
+        return FileOpResult::error(&e);
+    }
+    let result = run_job(
+        &app_handle,
+        job_id,
+        "remove",
+        move |app_handle, progress| {
+            let path = Path::new(&path);
+            progress.measure(path);
+            let result = app_handle.state::<FileHistory>().trash(path, progress);
+            if result.is_ok() {
+                progress.complete();
+            }
+            (result, progress.is_cancelled())
+        },
+    )
+    .await;
 
     match result {
-        Ok(_) => FileOpResult::success("Successfully deleted file/folder"),
-        Err(e) => FileOpResult::error(&format!("Failed to delete: {}", e)),
+        Ok((Ok(_), _)) => FileOpResult::success("Moved file/folder to trash"),
+        Ok((Err(_), true)) => FileOpResult::error("Delete cancelled, nothing was removed"),
+        Ok((Err(e), false)) | Err(e) => FileOpResult::error(&format!("Failed to delete: {}", e)),
     }
 }
 
 #[command]
-pub async fn create_dir(path: String) -> FileOpResult {
+pub async fn create_dir(app_handle: tauri::AppHandle, path: String) -> FileOpResult {
+    let path = match authorize(&app_handle, &path, "create folder").await {
+        Ok(path) => path,
+        Err(e) => return FileOpResult::error(&e),
+    };
+    let created = path
+        .ancestors()
+        .take_while(|dir| !path_exists(dir))
+        .last()
+        .map(Path::to_path_buf);
+
     match fs::create_dir_all(&path) {
-        Ok(_) => FileOpResult::success("Successfully created folder"),
+        Ok(_) => {
+            if let Some(created) = created {
+                app_handle
+                    .state::<FileHistory>()
+                    .record(FileOp::CreateDir { path, created });
+            }
+            FileOpResult::success("Successfully created folder")
+        }
         Err(e) => FileOpResult::error(&format!("Failed to create folder: {}", e)),
     }
 }
 
-#[command]
-pub async fn copy(source: String, destination: String) -> FileOpResult {
-    let source_path = PathBuf::from(&source);
-    let dest_path = PathBuf::from(&destination);
-
-    let result = if source_path.is_dir() {
-        copy_dir_recursive(&source_path, &dest_path)
-    } else {
-        fs::copy(&source, &destination).map(|_| ())
-    };
-
-    match result {
-        Ok(_) => FileOpResult::success("Successfully copied file/folder"),
-        Err(e) => FileOpResult::error(&format!("Failed to copy: {}", e)),
-    }
+async fn run_transfer(
+    app_handle: tauri::AppHandle,
+    source: String,
+    destination: String,
+    on_conflict: Option<ConflictMode>,
+    job_id: Option<String>,
+    moving: bool,
+) -> Result<TransferResult, String> {
+    let operation = if moving { "move" } else { "copy" };
+    authorize(&app_handle, &source, operation).await?;
+    authorize(&app_handle, &destination, operation).await?;
+    run_job(
+        &app_handle,
+        job_id,
+        operation,
+        move |app_handle, progress| {
+            let history = app_handle.state::<FileHistory>();
+            let mut transfer =
+                Transfer::new(&history, progress, on_conflict.unwrap_or_default(), moving);
+            transfer.run(Path::new(&source), Path::new(&destination));
+            transfer.finish()
+        },
+    )
+    .await
 }
 
-fn copy_dir_recursive(source: &PathBuf, destination: &PathBuf) -> std::io::Result<()> {
-    if !destination.exists() {
-        fs::create_dir_all(destination)?;
-    }
-
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let source_path = entry.path();
-        let dest_path = destination.join(entry.file_name());
-
-        if file_type.is_dir() {
-            copy_dir_recursive(&source_path, &dest_path)?;
-        } else {
-            fs::copy(&source_path, &dest_path)?;
-        }
-    }
-
-    Ok(())
+/// Copies a file or folder. Existing items at the destination are handled
+/// according to `on_conflict`, which defaults to reporting them. Runs as a
+/// job that reports `file_op_progress` events under `job_id`; when it's
+/// cancelled, what was copied so far is kept and reported.
+#[command]
+pub async fn copy(
+    app_handle: tauri::AppHandle,
+    source: String,
+    destination: String,
+    on_conflict: Option<ConflictMode>,
+    job_id: Option<String>,
+) -> Result<TransferResult, String> {
+    run_transfer(app_handle, source, destination, on_conflict, job_id, false).await
 }
 
+/// Moves a file or folder, handling existing items and progress like `copy`.
 #[command]
-pub async fn move_item(source: String, destination: String) -> FileOpResult {
-    // First try to rename (move) directly
-    if let Ok(_) = fs::rename(&source, &destination) {
-        return FileOpResult::success("Successfully moved file/folder");
-    }
-
-    // If rename fails (e.g., across devices), try copy + delete
-    match copy(source.clone(), destination).await {
-        FileOpResult { success: true, .. } => match remove(source).await {
-            FileOpResult { success: true, .. } => {
-                FileOpResult::success("Successfully moved file/folder")
-            }
-            FileOpResult { message, .. } => {
-                FileOpResult::error(&format!("Copied but failed to remove source: {}", message))
-            }
-        },
-        FileOpResult { message, .. } => FileOpResult::error(&message),
-    }
+pub async fn move_item(
+    app_handle: tauri::AppHandle,
+    source: String,
+    destination: String,
+    on_conflict: Option<ConflictMode>,
+    job_id: Option<String>,
+) -> Result<TransferResult, String> {
+    run_transfer(app_handle, source, destination, on_conflict, job_id, true).await
 }
 
 #[command]
-pub async fn index_workspace(_workspace_path: String) -> FileOpResult {
+pub async fn index_workspace(app_handle: tauri::AppHandle, workspace_path: String) -> FileOpResult {
+    if let Err(e) = authorize(&app_handle, &workspace_path, "index").await {
+        return FileOpResult::error(&e);
+    }
     // This is just a placeholder - the actual implementation would be in your AI service
     FileOpResult::success("Workspace indexing initiated")
 }
 
+/// Lists a folder `depth` levels deep. With `git_status`, entries inside a
+/// git work tree carry their status. Entries and subfolders that can't be
+/// read are listed with an `error` instead of failing the listing.
 #[tauri::command]
 pub async fn read_dir_metadata(
+    app_handle: tauri::AppHandle,
     path: String,
     depth: Option<u32>,
+    git_status: Option<bool>,
 ) -> Result<Vec<FileMetadata>, String> {
     let depth = depth.unwrap_or(1);
-    read_dir_recursive(PathBuf::from(path), depth).map_err(|e| e.to_string())
+    let path = authorize(&app_handle, &path, "read").await?;
+    let statuses = git_status
+        .unwrap_or(false)
+        .then(|| git::status_map(&path))
+        .flatten();
+    read_dir_recursive(path, depth, statuses.as_ref()).map_err(|e| e.to_string())
 }
 
-pub fn read_dir_recursive(path: PathBuf, depth: u32) -> std::io::Result<Vec<FileMetadata>> {
+pub fn read_dir_recursive(
+    path: PathBuf,
+    depth: u32,
+    statuses: Option<&HashMap<PathBuf, GitFileStatus>>,
+) -> std::io::Result<Vec<FileMetadata>> {
     let metadata = fs::symlink_metadata(&path)?;
     if !metadata.is_dir() {
         return Ok(Vec::new());
@@ -182,13 +365,25 @@ pub fn read_dir_recursive(path: PathBuf, depth: u32) -> std::io::Result<Vec<File
         return Ok(entries);
     }
 
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
+    for entry in fs::read_dir(&path)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                entries.push(FileMetadata::unreadable(path.clone(), e));
+                continue;
+            }
+        };
         let path = entry.path();
-        let mut metadata = FileMetadata::new(path.clone())?;
+        let mut metadata = FileMetadata::read(path.clone());
+        if let Some(statuses) = statuses {
+            metadata.git_status = git::status_for(statuses, &path, metadata.is_dir);
+        }
 
         if metadata.is_dir && depth > 1 {
-            metadata.children = Some(read_dir_recursive(path, depth - 1)?);
+            match read_dir_recursive(path, depth - 1, statuses) {
+                Ok(children) => metadata.children = Some(children),
+                Err(e) => metadata.error = Some(e.to_string()),
+            }
         }
 
         entries.push(metadata);
@@ -204,6 +399,17 @@ pub fn read_dir_recursive(path: PathBuf, depth: u32) -> std::io::Result<Vec<File
 }
 
 #[tauri::command]
-pub async fn read_file_metadata(path: String) -> Result<FileMetadata, String> {
-    FileMetadata::new(PathBuf::from(path)).map_err(|e| e.to_string())
+pub async fn read_file_metadata(
+    app_handle: tauri::AppHandle,
+    path: String,
+    git_status: Option<bool>,
+) -> Result<FileMetadata, String> {
+    let path = authorize(&app_handle, &path, "read").await?;
+    let mut metadata = FileMetadata::new(path.clone()).map_err(|e| e.to_string())?;
+    if git_status.unwrap_or(false) {
+        if let Some(statuses) = path.parent().and_then(git::status_map) {
+            metadata.git_status = git::status_for(&statuses, &path, metadata.is_dir);
+        }
+    }
+    Ok(metadata)
 }
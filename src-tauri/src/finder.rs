@@ -0,0 +1,221 @@
+use crate::path_guard::authorize;
+use crate::search::workspace_walker;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher, Utf32Str};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+const DEFAULT_FIND_LIMIT: usize = 50;
+// How long a list is trusted when some of its directories couldn't be
+// watched, e.g. once the system's watch limit is reached
+const UNWATCHED_TTL: Duration = Duration::from_secs(30);
+
+/// The files of one workspace, relative to its root. A watcher marks the
+/// list stale when files are created, removed or renamed, and the next
+/// lookup rebuilds it.
+pub struct FileIndex {
+    files: Arc<Vec<String>>,
+    stale: Arc<AtomicBool>,
+    built: Instant,
+    /// Whether every directory is watched; if not, the list also expires
+    /// after `UNWATCHED_TTL`
+    fully_watched: bool,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl FileIndex {
+    fn is_fresh(&self) -> bool {
+        !self.stale.load(Ordering::Relaxed)
+            && (self.fully_watched || self.built.elapsed() < UNWATCHED_TTL)
+    }
+}
+
+#[derive(Default)]
+pub struct FinderState {
+    pub indexes: Mutex<HashMap<PathBuf, FileIndex>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileMatch {
+    pub path: String,
+    pub relative_path: String,
+    pub name: String,
+    pub score: u32,
+    /// Character positions in `relative_path` that matched the query
+    pub highlights: Vec<usize>,
+}
+
+/// Lists the workspace's files along with the directories they were found
+/// in, leaving out everything the walker ignores.
+fn list_files(root: &Path) -> Result<(Vec<String>, Vec<PathBuf>), String> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in workspace_walker(root, &[], &[])?.build().flatten() {
+        match entry.file_type() {
+            Some(t) if t.is_dir() => dirs.push(entry.into_path()),
+            Some(t) if t.is_file() => {
+                if let Ok(path) = entry.path().strip_prefix(root) {
+                    files.push(path.to_string_lossy().replace('\\', "/"));
+                }
+            }
+            _ => {}
+        }
+    }
+    files.sort();
+    Ok((files, dirs))
+}
+
+/// Watches each of `dirs` on its own rather than the whole tree, so ignored
+/// folders like `target/` or `node_modules/` neither invalidate the list nor
+/// use up watches. Directories that can't be watched are skipped; the flag
+/// returned says whether all of them are covered.
+fn watch(dirs: &[PathBuf], stale: Arc<AtomicBool>) -> (Option<RecommendedWatcher>, bool) {
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if matches!(
+                event.kind,
+                EventKind::Create(_)
+                    | EventKind::Remove(_)
+                    | EventKind::Modify(ModifyKind::Name(_))
+            ) {
+                stale.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            println!("[find_files] Failed to create watcher: {}", e);
+            return (None, false);
+        }
+    };
+
+    let mut failed = 0;
+    for dir in dirs {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            if failed == 0 {
+                println!("[find_files] Failed to watch {}: {}", dir.display(), e);
+            }
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        println!(
+            "[find_files] {} of {} directories aren't watched, the file list will be refreshed periodically",
+            failed,
+            dirs.len()
+        );
+    }
+    (Some(watcher), failed == 0)
+}
+
+/// Returns the cached file list for `root`, building it on first use and
+/// again whenever the watcher saw the tree change.
+async fn workspace_files(
+    app_handle: &tauri::AppHandle,
+    root: &Path,
+) -> Result<Arc<Vec<String>>, String> {
+    let state = app_handle.state::<FinderState>();
+    let stale = {
+        let indexes = state.indexes.lock().map_err(|e| e.to_string())?;
+        match indexes.get(root) {
+            Some(index) if index.is_fresh() => {
+                return Ok(index.files.clone());
+            }
+            Some(index) => Some(index.stale.clone()),
+            None => None,
+        }
+    };
+
+    // Clear the flag before walking so changes made during the walk still
+    // trigger another rebuild
+    if let Some(stale) = &stale {
+        stale.store(false, Ordering::Relaxed);
+    }
+    let walk_root = root.to_path_buf();
+    let (files, dirs) = tauri::async_runtime::spawn_blocking(move || list_files(&walk_root))
+        .await
+        .map_err(|e| format!("Failed to list files: {}", e))??;
+    let files = Arc::new(files);
+
+    // Watch again on every rebuild so directories created since are covered.
+    // The old flag is kept so changes seen during the walk aren't lost.
+    let stale = stale.unwrap_or_default();
+    let (watcher, fully_watched) = watch(&dirs, stale.clone());
+    state.indexes.lock().map_err(|e| e.to_string())?.insert(
+        root.to_path_buf(),
+        FileIndex {
+            files: files.clone(),
+            stale,
+            built: Instant::now(),
+            fully_watched,
+            _watcher: watcher,
+        },
+    );
+    println!(
+        "[find_files] Indexed {} files in {}",
+        files.len(),
+        root.display()
+    );
+    Ok(files)
+}
+
+/// Fuzzy-matches `query` against the paths of every file in the workspace,
+/// best matches first.
+#[tauri::command]
+pub async fn find_files(
+    app_handle: tauri::AppHandle,
+    root: String,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<FileMatch>, String> {
+    let root = authorize(&app_handle, &root, "read")
+        .await?
+        .canonicalize()
+        .map_err(|e| format!("Failed to open workspace {}: {}", root, e))?;
+    let files = workspace_files(&app_handle, &root).await?;
+    let limit = limit.unwrap_or(DEFAULT_FIND_LIMIT);
+
+    let pattern = Pattern::parse(&query, CaseMatching::Smart, Normalization::Smart);
+    let mut matcher = Matcher::new(Config::DEFAULT.match_paths());
+    let mut buf = Vec::new();
+    let mut ranked: Vec<(u32, &String, Vec<u32>)> = Vec::new();
+    for file in files.iter() {
+        let mut indices = Vec::new();
+        if let Some(score) =
+            pattern.indices(Utf32Str::new(file, &mut buf), &mut matcher, &mut indices)
+        {
+            indices.sort_unstable();
+            indices.dedup();
+            ranked.push((score, file, indices));
+        }
+    }
+    ranked.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(a.1.len().cmp(&b.1.len()))
+            .then(a.1.cmp(b.1))
+    });
+
+    Ok(ranked
+        .into_iter()
+        .take(limit)
+        .map(|(score, relative_path, indices)| FileMatch {
+            path: root.join(relative_path).to_string_lossy().to_string(),
+            name: relative_path
+                .rsplit('/')
+                .next()
+                .unwrap_or(relative_path)
+                .to_string(),
+            relative_path: relative_path.clone(),
+            score,
+            highlights: indices.into_iter().map(|i| i as usize).collect(),
+        })
+        .collect())
+}
@@ -0,0 +1,998 @@
+use crate::path_guard::{authorize, resolve, PathGuard};
+use git2::build::CheckoutBuilder;
+use git2::{
+    ApplyLocation, ApplyOptions, Branch, BranchType, Commit, Diff, DiffOptions, ErrorCode, Oid,
+    Patch, Repository, Sort, Status, StatusOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+const DEFAULT_LOG_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitFileStatus {
+    Conflicted,
+    Untracked,
+    Added,
+    Deleted,
+    Renamed,
+    Modified,
+    Ignored,
+}
+
+impl GitFileStatus {
+    /// The status to show for a set of flags, most significant first.
+    pub fn from_flags(status: Status) -> Option<Self> {
+        if status.is_conflicted() {
+            Some(GitFileStatus::Conflicted)
+        } else if status.is_wt_new() {
+            Some(GitFileStatus::Untracked)
+        } else if status.is_index_new() {
+            Some(GitFileStatus::Added)
+        } else if status.intersects(Status::INDEX_DELETED | Status::WT_DELETED) {
+            Some(GitFileStatus::Deleted)
+        } else if status.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED) {
+            Some(GitFileStatus::Renamed)
+        } else if status.intersects(
+            Status::INDEX_MODIFIED
+                | Status::WT_MODIFIED
+                | Status::INDEX_TYPECHANGE
+                | Status::WT_TYPECHANGE,
+        ) {
+            Some(GitFileStatus::Modified)
+        } else if status.is_ignored() {
+            Some(GitFileStatus::Ignored)
+        } else {
+            None
+        }
+    }
+
+    /// The change staged in the index, if any.
+    pub fn staged(status: Status) -> Option<Self> {
+        if status.is_conflicted() {
+            Some(GitFileStatus::Conflicted)
+        } else if status.is_index_new() {
+            Some(GitFileStatus::Added)
+        } else if status.is_index_deleted() {
+            Some(GitFileStatus::Deleted)
+        } else if status.is_index_renamed() {
+            Some(GitFileStatus::Renamed)
+        } else if status.intersects(Status::INDEX_MODIFIED | Status::INDEX_TYPECHANGE) {
+            Some(GitFileStatus::Modified)
+        } else {
+            None
+        }
+    }
+
+    /// The change in the work tree that isn't staged yet, if any.
+    pub fn unstaged(status: Status) -> Option<Self> {
+        if status.is_conflicted() {
+            Some(GitFileStatus::Conflicted)
+        } else if status.is_wt_new() {
+            Some(GitFileStatus::Untracked)
+        } else if status.is_wt_deleted() {
+            Some(GitFileStatus::Deleted)
+        } else if status.is_wt_renamed() {
+            Some(GitFileStatus::Renamed)
+        } else if status.intersects(Status::WT_MODIFIED | Status::WT_TYPECHANGE) {
+            Some(GitFileStatus::Modified)
+        } else {
+            None
+        }
+    }
+}
+
+/// Statuses of the changed files under `dir`, by absolute path, or None if
+/// `dir` isn't inside a git work tree. Folders aren't listed; see
+/// `status_for`.
+pub fn status_map(dir: &Path) -> Option<HashMap<PathBuf, GitFileStatus>> {
+    let repo = Repository::discover(dir).ok()?;
+    let workdir = repo.workdir()?.canonicalize().ok()?;
+    let dir = dir.canonicalize().ok()?;
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    if let Ok(relative) = dir.strip_prefix(&workdir) {
+        if !relative.as_os_str().is_empty() {
+            options.pathspec(relative);
+        }
+    }
+
+    let statuses = repo.statuses(Some(&mut options)).ok()?;
+    Some(
+        statuses
+            .iter()
+            .filter_map(|entry| {
+                let status = GitFileStatus::from_flags(entry.status())?;
+                Some((workdir.join(entry.path()?), status))
+            })
+            .collect(),
+    )
+}
+
+/// The status of a file, or of a folder as `Modified` when anything inside
+/// it changed.
+pub fn status_for(
+    statuses: &HashMap<PathBuf, GitFileStatus>,
+    path: &Path,
+    is_dir: bool,
+) -> Option<GitFileStatus> {
+    // Only the parent is resolved so a symlink keeps its own status
+    let path = path.parent()?.canonicalize().ok()?.join(path.file_name()?);
+    if !is_dir {
+        return statuses.get(&path).copied();
+    }
+    statuses
+        .keys()
+        .any(|changed| changed.starts_with(&path))
+        .then_some(GitFileStatus::Modified)
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitStatusEntry {
+    /// Relative to the repository root
+    pub path: String,
+    /// The previous path of a renamed file
+    pub old_path: Option<String>,
+    pub staged: Option<GitFileStatus>,
+    pub unstaged: Option<GitFileStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitStatus {
+    pub root: String,
+    /// None when HEAD is detached
+    pub branch: Option<String>,
+    /// None before the first commit
+    pub head: Option<String>,
+    pub upstream: Option<String>,
+    /// Commits ahead of and behind the upstream, as last fetched
+    pub ahead: usize,
+    pub behind: usize,
+    pub files: Vec<GitStatusEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Context,
+    Addition,
+    Deletion,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub content: String,
+    pub old_line: Option<u32>,
+    pub new_line: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffHunk {
+    /// Pass to `git_stage_file`/`git_unstage_file` to pick this hunk
+    pub index: usize,
+    pub header: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub old_path: Option<String>,
+    pub binary: bool,
+    pub hunks: Vec<DiffHunk>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GitCommit {
+    pub id: String,
+    pub short_id: String,
+    pub summary: String,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub parents: Vec<String>,
+}
+
+impl GitCommit {
+    fn new(commit: &Commit) -> Self {
+        let id = commit.id().to_string();
+        let author = commit.author();
+        GitCommit {
+            short_id: id[..7].to_string(),
+            id,
+            summary: commit.summary().unwrap_or_default().to_string(),
+            message: commit.message().unwrap_or_default().to_string(),
+            author_name: author.name().unwrap_or_default().to_string(),
+            author_email: author.email().unwrap_or_default().to_string(),
+            time: author.when().seconds(),
+            parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitBranch {
+    /// Remote branches are named `<remote>/<branch>`
+    pub name: String,
+    pub remote: bool,
+    pub is_head: bool,
+    pub upstream: Option<String>,
+    pub commit: Option<GitCommit>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GitLogPage {
+    pub commits: Vec<GitCommit>,
+    pub offset: usize,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlameHunk {
+    /// 1-based, in the file as it is in the work tree
+    pub start_line: usize,
+    pub lines: usize,
+    /// None for lines that aren't committed yet
+    pub commit: Option<GitCommit>,
+}
+
+fn git_error(action: &str) -> impl Fn(git2::Error) -> String + '_ {
+    move |e| format!("Failed to {}: {}", action, e.message())
+}
+
+/// Opens the repository containing `path` on a blocking thread, once the
+/// path guard allows `operation` on it.
+async fn with_repo<T, F>(
+    app_handle: &tauri::AppHandle,
+    path: &str,
+    operation: &str,
+    work: F,
+) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Repository) -> Result<T, String> + Send + 'static,
+{
+    let path = authorize(app_handle, path, operation).await?;
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let repo = open_repo(&path, &app_handle.state::<PathGuard>())?;
+        work(&repo)
+    })
+    .await
+    .map_err(|e| format!("Git task failed: {}", e))?
+}
+
+/// The repository containing `path`. Discovery walks up past the authorized
+/// folder, so a repository found above the allowed folders, like a dotfiles
+/// repo in the home folder, is refused.
+fn open_repo(path: &Path, guard: &PathGuard) -> Result<Repository, String> {
+    let repo = Repository::discover(path).map_err(git_error("open git repository"))?;
+    if !guard.allows(&workdir(&repo)?) {
+        return Err(format!(
+            "{} is not in a git repository inside the workspace",
+            path.display()
+        ));
+    }
+    Ok(repo)
+}
+
+fn workdir(repo: &Repository) -> Result<PathBuf, String> {
+    repo.workdir()
+        .ok_or_else(|| "Bare repositories aren't supported".to_string())?
+        .canonicalize()
+        .map_err(|e| format!("Failed to read repository folder: {}", e))
+}
+
+/// `file`, absolute or relative to the repository root, as a path relative
+/// to the root.
+fn repo_path(repo: &Repository, file: &str) -> Result<PathBuf, String> {
+    let workdir = workdir(repo)?;
+    let path = resolve(&workdir.join(file))?;
+    path.strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| format!("{} is outside the repository", path.display()))
+}
+
+fn head_commit(repo: &Repository) -> Result<Option<Commit<'_>>, String> {
+    match repo.head() {
+        Ok(head) => head
+            .peel_to_commit()
+            .map(Some)
+            .map_err(git_error("read HEAD")),
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+        Err(e) => Err(git_error("read HEAD")(e)),
+    }
+}
+
+/// The staged (HEAD to index) or unstaged (index to work tree) changes of
+/// one file. `reverse` swaps the sides, for unstaging.
+fn diff_file<'a>(
+    repo: &'a Repository,
+    path: &Path,
+    staged: bool,
+    reverse: bool,
+) -> Result<Diff<'a>, String> {
+    let mut options = DiffOptions::new();
+    options
+        .pathspec(path)
+        .disable_pathspec_match(true)
+        .include_untracked(true)
+        .show_untracked_content(true)
+        .reverse(reverse);
+    let index = repo.index().map_err(git_error("read index"))?;
+    let diff = if staged {
+        let tree = head_commit(repo)?
+            .map(|commit| commit.tree())
+            .transpose()
+            .map_err(git_error("read HEAD"))?;
+        repo.diff_tree_to_index(tree.as_ref(), Some(&index), Some(&mut options))
+    } else {
+        repo.diff_index_to_workdir(Some(&index), Some(&mut options))
+    };
+    diff.map_err(git_error("diff"))
+}
+
+/// Applies only the hunks of `diff` at `selected` to the index.
+fn apply_hunks(repo: &Repository, diff: &Diff, selected: &[usize]) -> Result<(), String> {
+    let selected: HashSet<usize> = selected.iter().copied().collect();
+    let mut next = 0;
+    let mut options = ApplyOptions::new();
+    options.hunk_callback(|_| {
+        next += 1;
+        selected.contains(&(next - 1))
+    });
+    repo.apply(diff, ApplyLocation::Index, Some(&mut options))
+        .map_err(git_error("apply changes to the index"))
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+/// Initializes a git repository in `path`.
+#[tauri::command]
+pub async fn git_init(app_handle: tauri::AppHandle, path: String) -> Result<(), String> {
+    let path = authorize(&app_handle, &path, "write").await?;
+    Repository::init(&path).map_err(git_error("initialize repository"))?;
+    println!("[git_init] Initialized repository in {}", path.display());
+    Ok(())
+}
+
+/// The current branch and the staged and unstaged changes of the
+/// repository containing `path`.
+#[tauri::command]
+pub async fn git_status(app_handle: tauri::AppHandle, path: String) -> Result<GitStatus, String> {
+    with_repo(&app_handle, &path, "read", |repo| {
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false)
+            .renames_head_to_index(true)
+            .renames_index_to_workdir(true);
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .map_err(git_error("read status"))?;
+        let files = statuses
+            .iter()
+            .filter_map(|entry| {
+                let status = entry.status();
+                let old_path = entry
+                    .head_to_index()
+                    .or_else(|| entry.index_to_workdir())
+                    .filter(|_| status.is_index_renamed() || status.is_wt_renamed())
+                    .and_then(|delta| delta.old_file().path())
+                    .map(|path| path.to_string_lossy().to_string());
+                Some(GitStatusEntry {
+                    path: entry.path()?.to_string(),
+                    old_path,
+                    staged: GitFileStatus::staged(status),
+                    unstaged: GitFileStatus::unstaged(status),
+                })
+            })
+            .collect();
+
+        let head = repo.head().ok();
+        let branch = head
+            .as_ref()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand())
+            .map(str::to_string)
+            // Before the first commit HEAD points at a branch that doesn't exist yet
+            .or_else(|| {
+                repo.find_reference("HEAD")
+                    .ok()?
+                    .symbolic_target()?
+                    .strip_prefix("refs/heads/")
+                    .map(str::to_string)
+            });
+
+        let mut status = GitStatus {
+            root: workdir(repo)?.to_string_lossy().to_string(),
+            branch,
+            head: head
+                .as_ref()
+                .and_then(|head| head.target())
+                .map(|id| id.to_string()),
+            upstream: None,
+            ahead: 0,
+            behind: 0,
+            files,
+        };
+        if let Some(name) = &status.branch {
+            if let Ok(upstream) = repo
+                .find_branch(name, BranchType::Local)
+                .and_then(|branch| branch.upstream())
+            {
+                status.upstream = upstream.name().ok().flatten().map(str::to_string);
+                if let (Some(local), Some(remote)) = (
+                    head.as_ref().and_then(|head| head.target()),
+                    upstream.get().target(),
+                ) {
+                    (status.ahead, status.behind) =
+                        repo.graph_ahead_behind(local, remote).unwrap_or((0, 0));
+                }
+            }
+        }
+        Ok(status)
+    })
+    .await
+}
+
+/// The staged or unstaged changes of one file, split into hunks.
+#[tauri::command]
+pub async fn git_diff(
+    app_handle: tauri::AppHandle,
+    path: String,
+    file: String,
+    staged: bool,
+) -> Result<FileDiff, String> {
+    with_repo(&app_handle, &path, "read", move |repo| {
+        let relative = repo_path(repo, &file)?;
+        let diff = diff_file(repo, &relative, staged, false)?;
+        let mut file_diff = FileDiff {
+            path: relative.to_string_lossy().to_string(),
+            old_path: None,
+            binary: false,
+            hunks: Vec::new(),
+        };
+        // A file without changes has no delta to build a patch from
+        if diff.deltas().len() == 0 {
+            return Ok(file_diff);
+        }
+        let patch = match Patch::from_diff(&diff, 0).map_err(git_error("diff"))? {
+            Some(patch) => patch,
+            None => return Ok(file_diff),
+        };
+
+        let delta = patch.delta();
+        file_diff.binary = delta.flags().is_binary();
+        file_diff.old_path = delta
+            .old_file()
+            .path()
+            .filter(|old_path| *old_path != relative)
+            .map(|old_path| old_path.to_string_lossy().to_string());
+
+        for index in 0..patch.num_hunks() {
+            let (hunk, line_count) = patch.hunk(index).map_err(git_error("diff"))?;
+            let mut lines = Vec::with_capacity(line_count);
+            for line_index in 0..line_count {
+                let line = patch
+                    .line_in_hunk(index, line_index)
+                    .map_err(git_error("diff"))?;
+                let kind = match line.origin() {
+                    ' ' => DiffLineKind::Context,
+                    '+' => DiffLineKind::Addition,
+                    '-' => DiffLineKind::Deletion,
+                    // "No newline at end of file" markers
+                    _ => continue,
+                };
+                lines.push(DiffLine {
+                    kind,
+                    content: lossy(line.content())
+                        .trim_end_matches(['\r', '\n'])
+                        .to_string(),
+                    old_line: line.old_lineno(),
+                    new_line: line.new_lineno(),
+                });
+            }
+            file_diff.hunks.push(DiffHunk {
+                index,
+                header: lossy(hunk.header()).trim_end().to_string(),
+                old_start: hunk.old_start(),
+                old_lines: hunk.old_lines(),
+                new_start: hunk.new_start(),
+                new_lines: hunk.new_lines(),
+                lines,
+            });
+        }
+        Ok(file_diff)
+    })
+    .await
+}
+
+/// Stages a file, or only the unstaged hunks at `hunks` as numbered by
+/// `git_diff`.
+#[tauri::command]
+pub async fn git_stage_file(
+    app_handle: tauri::AppHandle,
+    path: String,
+    file: String,
+    hunks: Option<Vec<usize>>,
+) -> Result<(), String> {
+    with_repo(&app_handle, &path, "write", move |repo| {
+        stage(repo, &file, hunks.as_deref())
+    })
+    .await
+}
+
+fn stage(repo: &Repository, file: &str, hunks: Option<&[usize]>) -> Result<(), String> {
+    let relative = repo_path(repo, file)?;
+    if let Some(hunks) = hunks {
+        let diff = diff_file(repo, &relative, false, false)?;
+        return apply_hunks(repo, &diff, hunks);
+    }
+
+    let mut index = repo.index().map_err(git_error("read index"))?;
+    // A file deleted from the work tree is staged by removing it
+    let staged = if workdir(repo)?.join(&relative).symlink_metadata().is_ok() {
+        index.add_path(&relative)
+    } else {
+        index.remove_path(&relative)
+    };
+    staged.map_err(git_error("stage file"))?;
+    index.write().map_err(git_error("write index"))
+}
+
+/// Unstages a file, or only the staged hunks at `hunks` as numbered by
+/// `git_diff`. The work tree is left as is.
+#[tauri::command]
+pub async fn git_unstage_file(
+    app_handle: tauri::AppHandle,
+    path: String,
+    file: String,
+    hunks: Option<Vec<usize>>,
+) -> Result<(), String> {
+    with_repo(&app_handle, &path, "write", move |repo| {
+        unstage(repo, &file, hunks.as_deref())
+    })
+    .await
+}
+
+fn unstage(repo: &Repository, file: &str, hunks: Option<&[usize]>) -> Result<(), String> {
+    let relative = repo_path(repo, file)?;
+    if let Some(hunks) = hunks {
+        let diff = diff_file(repo, &relative, true, true)?;
+        return apply_hunks(repo, &diff, hunks);
+    }
+
+    match head_commit(repo)? {
+        Some(head) => repo
+            .reset_default(Some(head.as_object()), [relative.as_path()])
+            .map_err(git_error("unstage file")),
+        None => {
+            let mut index = repo.index().map_err(git_error("read index"))?;
+            index
+                .remove_path(&relative)
+                .map_err(git_error("unstage file"))?;
+            index.write().map_err(git_error("write index"))
+        }
+    }
+}
+
+/// Commits the staged changes with the user's configured git identity.
+#[tauri::command]
+pub async fn git_commit(
+    app_handle: tauri::AppHandle,
+    path: String,
+    message: String,
+) -> Result<GitCommit, String> {
+    with_repo(&app_handle, &path, "write", move |repo| {
+        commit(repo, &message)
+    })
+    .await
+}
+
+fn commit(repo: &Repository, message: &str) -> Result<GitCommit, String> {
+    if message.trim().is_empty() {
+        return Err("Commit message can't be empty".to_string());
+    }
+    let mut index = repo.index().map_err(git_error("read index"))?;
+    if index.has_conflicts() {
+        return Err("Resolve the conflicts before committing".to_string());
+    }
+    let tree_id = index.write_tree().map_err(git_error("write tree"))?;
+    let parent = head_commit(repo)?;
+    let unchanged = match &parent {
+        Some(parent) => parent.tree_id() == tree_id,
+        None => index.is_empty(),
+    };
+    if unchanged {
+        return Err("Nothing to commit".to_string());
+    }
+
+    let tree = repo.find_tree(tree_id).map_err(git_error("write tree"))?;
+    let signature = repo.signature().map_err(|e| {
+        format!(
+            "Set user.name and user.email in your git config to commit: {}",
+            e.message()
+        )
+    })?;
+    let parents: Vec<&Commit> = parent.iter().collect();
+    let id = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .map_err(git_error("commit"))?;
+    let commit = repo.find_commit(id).map_err(git_error("commit"))?;
+    println!("[git_commit] Committed {}", id);
+    Ok(GitCommit::new(&commit))
+}
+
+/// Local and remote-tracking branches, as of the last fetch.
+#[tauri::command]
+pub async fn git_branches(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<Vec<GitBranch>, String> {
+    with_repo(&app_handle, &path, "read", |repo| {
+        let mut branches = Vec::new();
+        for branch in repo.branches(None).map_err(git_error("list branches"))? {
+            let (branch, kind) = branch.map_err(git_error("list branches"))?;
+            // Skip `origin/HEAD` and the like
+            if branch.get().symbolic_target().is_some() {
+                continue;
+            }
+            let Some(name) = branch.name().ok().flatten().map(str::to_string) else {
+                continue;
+            };
+            branches.push(GitBranch {
+                name,
+                remote: kind == BranchType::Remote,
+                is_head: branch.is_head(),
+                upstream: branch
+                    .upstream()
+                    .ok()
+                    .and_then(|upstream| upstream.name().ok().flatten().map(str::to_string)),
+                commit: branch
+                    .get()
+                    .peel_to_commit()
+                    .ok()
+                    .map(|commit| GitCommit::new(&commit)),
+            });
+        }
+        branches.sort_by(|a, b| a.remote.cmp(&b.remote).then_with(|| a.name.cmp(&b.name)));
+        Ok(branches)
+    })
+    .await
+}
+
+/// Checks out `branch` without touching local changes that would be
+/// overwritten, in which case it fails. A remote branch gets a local
+/// branch tracking it.
+fn switch_to(repo: &Repository, name: &str) -> Result<(), String> {
+    let branch = match repo.find_branch(name, BranchType::Local) {
+        Ok(branch) => branch,
+        Err(_) => {
+            let remote = repo
+                .find_branch(name, BranchType::Remote)
+                .map_err(|_| format!("No branch named {}", name))?;
+            let commit = remote
+                .get()
+                .peel_to_commit()
+                .map_err(git_error("read branch"))?;
+            let local_name = name.split_once('/').map_or(name, |(_, branch)| branch);
+            let mut local = repo
+                .branch(local_name, &commit, false)
+                .map_err(git_error("create branch"))?;
+            local
+                .set_upstream(Some(name))
+                .map_err(git_error("set upstream"))?;
+            local
+        }
+    };
+
+    let reference = branch.get();
+    let commit = reference
+        .peel_to_commit()
+        .map_err(git_error("read branch"))?;
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))
+        .map_err(git_error("switch branch"))?;
+    let reference_name = reference
+        .name()
+        .ok_or_else(|| format!("Invalid branch name {}", name))?;
+    repo.set_head(reference_name)
+        .map_err(git_error("switch branch"))
+}
+
+#[tauri::command]
+pub async fn git_switch_branch(
+    app_handle: tauri::AppHandle,
+    path: String,
+    branch: String,
+) -> Result<(), String> {
+    with_repo(&app_handle, &path, "write", move |repo| {
+        switch_to(repo, &branch)?;
+        println!("[git_switch_branch] Switched to {}", branch);
+        Ok(())
+    })
+    .await
+}
+
+/// Creates a branch at `start_point` (any revision, HEAD by default) and
+/// optionally switches to it.
+#[tauri::command]
+pub async fn git_create_branch(
+    app_handle: tauri::AppHandle,
+    path: String,
+    name: String,
+    start_point: Option<String>,
+    checkout: Option<bool>,
+) -> Result<GitBranch, String> {
+    with_repo(&app_handle, &path, "write", move |repo| {
+        if !Branch::name_is_valid(&name).unwrap_or(false) {
+            return Err(format!("{} isn't a valid branch name", name));
+        }
+        let commit = repo
+            .revparse_single(start_point.as_deref().unwrap_or("HEAD"))
+            .and_then(|object| object.peel_to_commit())
+            .map_err(git_error("find start point"))?;
+        repo.branch(&name, &commit, false)
+            .map_err(git_error("create branch"))?;
+        let checkout = checkout.unwrap_or(false);
+        if checkout {
+            switch_to(repo, &name)?;
+        }
+        println!("[git_create_branch] Created {} at {}", name, commit.id());
+        Ok(GitBranch {
+            name,
+            remote: false,
+            is_head: checkout,
+            upstream: None,
+            commit: Some(GitCommit::new(&commit)),
+        })
+    })
+    .await
+}
+
+/// One page of the history of HEAD, newest first.
+#[tauri::command]
+pub async fn git_log(
+    app_handle: tauri::AppHandle,
+    path: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<GitLogPage, String> {
+    with_repo(&app_handle, &path, "read", move |repo| {
+        log_page(
+            repo,
+            offset.unwrap_or(0),
+            limit.unwrap_or(DEFAULT_LOG_PAGE_SIZE),
+        )
+    })
+    .await
+}
+
+fn log_page(repo: &Repository, offset: usize, limit: usize) -> Result<GitLogPage, String> {
+    let mut page = GitLogPage {
+        commits: Vec::new(),
+        offset,
+        has_more: false,
+    };
+    if head_commit(repo)?.is_none() {
+        return Ok(page);
+    }
+
+    let mut revwalk = repo.revwalk().map_err(git_error("read history"))?;
+    revwalk
+        .set_sorting(Sort::TIME)
+        .map_err(git_error("read history"))?;
+    revwalk.push_head().map_err(git_error("read history"))?;
+    // One extra commit tells whether there's another page
+    for id in revwalk.skip(offset).take(limit + 1) {
+        let id = id.map_err(git_error("read history"))?;
+        if page.commits.len() == limit {
+            page.has_more = true;
+            break;
+        }
+        let commit = repo.find_commit(id).map_err(git_error("read commit"))?;
+        page.commits.push(GitCommit::new(&commit));
+    }
+    Ok(page)
+}
+
+/// Who last changed each line of `file`, including uncommitted edits in
+/// the work tree.
+#[tauri::command]
+pub async fn git_blame(
+    app_handle: tauri::AppHandle,
+    path: String,
+    file: String,
+) -> Result<Vec<BlameHunk>, String> {
+    with_repo(&app_handle, &path, "read", move |repo| {
+        let relative = repo_path(repo, &file)?;
+        let committed = repo
+            .blame_file(&relative, None)
+            .map_err(git_error("blame file"))?;
+        let blame = match fs::read(workdir(repo)?.join(&relative)) {
+            Ok(content) => committed
+                .blame_buffer(&content)
+                .map_err(git_error("blame file"))?,
+            Err(_) => committed,
+        };
+
+        let mut commits: HashMap<Oid, GitCommit> = HashMap::new();
+        let mut hunks = Vec::new();
+        for hunk in blame.iter() {
+            let id = hunk.final_commit_id();
+            if !id.is_zero() && !commits.contains_key(&id) {
+                let commit = repo.find_commit(id).map_err(git_error("read commit"))?;
+                commits.insert(id, GitCommit::new(&commit));
+            }
+            hunks.push(BlameHunk {
+                start_line: hunk.final_start_line(),
+                lines: hunk.lines_in_hunk(),
+                commit: commits.get(&id).cloned(),
+            });
+        }
+        Ok(hunks)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_repo() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        (dir, repo)
+    }
+
+    fn write(repo: &Repository, file: &str, contents: &str) {
+        fs::write(repo.workdir().unwrap().join(file), contents).unwrap();
+    }
+
+    /// The new-side start line of each hunk in a file's staged or unstaged
+    /// changes.
+    fn hunk_starts(repo: &Repository, file: &str, staged: bool) -> Vec<u32> {
+        let diff = diff_file(repo, Path::new(file), staged, false).unwrap();
+        if diff.deltas().len() == 0 {
+            return Vec::new();
+        }
+        let Some(patch) = Patch::from_diff(&diff, 0).unwrap() else {
+            return Vec::new();
+        };
+        (0..patch.num_hunks())
+            .map(|index| patch.hunk(index).unwrap().0.new_start())
+            .collect()
+    }
+
+    /// A committed file of 20 lines with lines 2 and 19 changed in the work
+    /// tree, far enough apart to be separate hunks.
+    fn two_hunks() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = test_repo();
+        let lines: Vec<String> = (1..=20).map(|n| format!("line {}\n", n)).collect();
+        write(&repo, "file.txt", &lines.concat());
+        stage(&repo, "file.txt", None).unwrap();
+        commit(&repo, "Add file").unwrap();
+
+        let mut changed = lines;
+        changed[1] = "changed 2\n".to_string();
+        changed[18] = "changed 19\n".to_string();
+        write(&repo, "file.txt", &changed.concat());
+        (dir, repo)
+    }
+
+    #[test]
+    fn staging_one_hunk_leaves_the_other_unstaged() {
+        let (_dir, repo) = two_hunks();
+        assert_eq!(hunk_starts(&repo, "file.txt", false).len(), 2);
+
+        stage(&repo, "file.txt", Some(&[1])).unwrap();
+        let staged = hunk_starts(&repo, "file.txt", true);
+        let unstaged = hunk_starts(&repo, "file.txt", false);
+        assert_eq!(staged.len(), 1);
+        assert_eq!(unstaged.len(), 1);
+        assert!(staged[0] > unstaged[0]);
+    }
+
+    #[test]
+    fn unstaging_a_hunk_puts_it_back_in_the_work_tree_changes() {
+        let (_dir, repo) = two_hunks();
+        stage(&repo, "file.txt", None).unwrap();
+        assert_eq!(hunk_starts(&repo, "file.txt", true).len(), 2);
+
+        unstage(&repo, "file.txt", Some(&[0])).unwrap();
+        assert_eq!(hunk_starts(&repo, "file.txt", true).len(), 1);
+        assert_eq!(hunk_starts(&repo, "file.txt", false).len(), 1);
+
+        unstage(&repo, "file.txt", Some(&[0])).unwrap();
+        assert!(hunk_starts(&repo, "file.txt", true).is_empty());
+        assert_eq!(hunk_starts(&repo, "file.txt", false).len(), 2);
+        let contents = fs::read_to_string(repo.workdir().unwrap().join("file.txt")).unwrap();
+        assert!(contents.contains("changed 2\n") && contents.contains("changed 19\n"));
+    }
+
+    #[test]
+    fn first_commit_on_an_unborn_head() {
+        let (_dir, repo) = test_repo();
+        assert!(head_commit(&repo).unwrap().is_none());
+        assert_eq!(commit(&repo, "Empty").unwrap_err(), "Nothing to commit");
+
+        write(&repo, "a.txt", "a\n");
+        stage(&repo, "a.txt", None).unwrap();
+        let first = commit(&repo, "First").unwrap();
+        let head = head_commit(&repo).unwrap().unwrap();
+        assert_eq!(head.id().to_string(), first.id);
+        assert_eq!(head.parent_count(), 0);
+        assert_eq!(commit(&repo, "Again").unwrap_err(), "Nothing to commit");
+    }
+
+    #[test]
+    fn log_pages_through_history() {
+        let (_dir, repo) = test_repo();
+        assert!(log_page(&repo, 0, 2).unwrap().commits.is_empty());
+        for n in 0..3 {
+            write(&repo, "a.txt", &n.to_string());
+            stage(&repo, "a.txt", None).unwrap();
+            commit(&repo, &format!("Commit {}", n)).unwrap();
+        }
+
+        let first = log_page(&repo, 0, 2).unwrap();
+        assert_eq!(first.commits.len(), 2);
+        assert!(first.has_more);
+        let second = log_page(&repo, 2, 2).unwrap();
+        assert_eq!(second.commits.len(), 1);
+        assert!(!second.has_more);
+
+        let mut ids: Vec<&str> = first
+            .commits
+            .iter()
+            .chain(&second.commits)
+            .map(|commit| commit.id.as_str())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn repositories_above_the_allowed_folders_are_refused() {
+        let (dir, _repo) = test_repo();
+        let root = dir.path().canonicalize().unwrap();
+        let inner = root.join("inner");
+        fs::create_dir(&inner).unwrap();
+
+        let guard = PathGuard::new(vec![inner.clone()]);
+        let error = open_repo(&inner, &guard).err().unwrap();
+        assert!(error.contains("not in a git repository inside the workspace"));
+
+        let guard = PathGuard::new(vec![root.clone()]);
+        assert!(open_repo(&inner, &guard).is_ok());
+    }
+}
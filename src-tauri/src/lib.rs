@@ -1,23 +1,66 @@
+mod asciicast;
 mod chat;
+mod chat_export;
 mod commands;
 mod config;
+mod context;
 mod db;
+mod file_history;
+mod file_jobs;
 mod file_ops;
+mod finder;
+mod git;
+mod listing;
 mod llm;
+mod migrations;
 mod models;
+mod path_guard;
+mod prompts;
+mod replace;
 mod scraper;
+mod search;
 mod setup;
+mod shell_integration;
 mod terminal;
+mod text_file;
+mod transfer;
 use chat::{
-    get_chat_history, get_sessions, insert_message, update_message_response, update_session_title,
+    add_message, archive_session, chat_stats, create_session, delete_session, edit_message,
+    get_chat_history, get_database_status, get_sessions, insert_message, pin_session,
+    regenerate_response, regenerate_titles, search_chats, switch_branch, update_message_response,
+    update_session_title,
 };
+use chat_export::{export_all_sessions, export_session, import_sessions};
 use commands::initialize_project;
 use config::{get_project_directory_command, set_project_directory};
+use context::{build_context, count_tokens, set_context_model};
+use file_history::{redo_file_op, undo_last_file_op};
+use file_jobs::cancel_file_op;
 use file_ops::{
     copy, create_dir, index_workspace, move_item, read_dir_metadata, read_file_metadata, remove,
     rename,
 };
-use terminal::{close_pty, resize_pty, start_pty, write_to_pty};
+use finder::find_files;
+use git::{
+    git_blame, git_branches, git_commit, git_create_branch, git_diff, git_init, git_log,
+    git_stage_file, git_status, git_switch_branch, git_unstage_file,
+};
+use listing::list_dir;
+use path_guard::open_workspace;
+use prompts::{
+    create_prompt_preset, delete_prompt_preset, list_prompt_presets, render_session_prompt,
+    set_session_preset, update_prompt_preset,
+};
+use replace::{
+    apply_workspace_replace, discard_workspace_replace, replace_in_workspace,
+    undo_workspace_replace,
+};
+use search::{cancel_workspace_search, search_workspace};
+use terminal::{
+    close_pty, exec_in_pty, export_pty_log, get_pty_commands, list_recordings, replay_recording,
+    resize_pty, search_pty_output, start_pty, write_to_pty,
+};
+use text_file::{read_text_file, write_text_file};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -32,24 +75,81 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             // File operations
             read_dir_metadata,
+            list_dir,
             read_file_metadata,
+            read_text_file,
+            write_text_file,
             rename,
             remove,
             create_dir,
             copy,
             move_item,
+            undo_last_file_op,
+            redo_file_op,
+            cancel_file_op,
+            open_workspace,
             index_workspace,
+            find_files,
+            search_workspace,
+            cancel_workspace_search,
+            replace_in_workspace,
+            apply_workspace_replace,
+            discard_workspace_replace,
+            undo_workspace_replace,
+            // Git
+            git_init,
+            git_status,
+            git_diff,
+            git_stage_file,
+            git_unstage_file,
+            git_commit,
+            git_branches,
+            git_switch_branch,
+            git_create_branch,
+            git_log,
+            git_blame,
             // Chat functionality
             insert_message,
+            add_message,
             get_chat_history,
             get_sessions,
             update_message_response,
             update_session_title,
+            regenerate_titles,
+            get_database_status,
+            create_session,
+            delete_session,
+            archive_session,
+            pin_session,
+            search_chats,
+            chat_stats,
+            regenerate_response,
+            edit_message,
+            switch_branch,
+            export_session,
+            export_all_sessions,
+            import_sessions,
+            set_context_model,
+            count_tokens,
+            build_context,
+            // Prompt presets
+            list_prompt_presets,
+            create_prompt_preset,
+            update_prompt_preset,
+            delete_prompt_preset,
+            set_session_preset,
+            render_session_prompt,
             // Terminal operations
             start_pty,
             write_to_pty,
             close_pty,
             resize_pty,
+            get_pty_commands,
+            exec_in_pty,
+            search_pty_output,
+            export_pty_log,
+            list_recordings,
+            replay_recording,
             // Project initialization
             initialize_project,
             // Configuration management
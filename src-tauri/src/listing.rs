@@ -0,0 +1,295 @@
+use crate::file_ops::FileMetadata;
+use crate::git;
+use crate::path_guard::authorize;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const DEFAULT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Name,
+    /// Like name, but with numbers compared by value, so `file2` comes
+    /// before `file10`
+    #[default]
+    Natural,
+    Modified,
+    Size,
+    /// By extension, then name
+    Type,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirPage {
+    pub path: String,
+    pub entries: Vec<FileMetadata>,
+    /// Entries in the folder after filtering, across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub has_more: bool,
+}
+
+/// What's needed to sort an entry; full metadata is only read for the
+/// entries on the requested page.
+struct Candidate {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+    modified: SystemTime,
+    size: u64,
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(c);
+    }
+    number
+}
+
+/// Compares case-insensitively, treating runs of digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn compare(a: &Candidate, b: &Candidate, sort: SortKey) -> Ordering {
+    let by_name = || a.name.to_lowercase().cmp(&b.name.to_lowercase());
+    match sort {
+        SortKey::Name => by_name(),
+        SortKey::Natural => natural_cmp(&a.name, &b.name),
+        SortKey::Modified => a.modified.cmp(&b.modified).then_with(by_name),
+        SortKey::Size => a.size.cmp(&b.size).then_with(by_name),
+        SortKey::Type => extension(&a.name)
+            .cmp(&extension(&b.name))
+            .then_with(|| natural_cmp(&a.name, &b.name)),
+    }
+}
+
+/// The entry a walk error is about, if the error says.
+fn error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            error_path(err)
+        }
+        ignore::Error::Partial(errors) => errors.iter().find_map(error_path),
+        _ => None,
+    }
+}
+
+/// Which of the `listed` entries and of the `unreadable` ones that follow
+/// them fall on the page starting at `offset`.
+fn page_ranges(
+    listed: usize,
+    unreadable: usize,
+    offset: usize,
+    limit: usize,
+) -> (Range<usize>, Range<usize>) {
+    let end = offset.saturating_add(limit);
+    (
+        offset.min(listed)..end.min(listed),
+        offset.saturating_sub(listed).min(unreadable)..end.saturating_sub(listed).min(unreadable),
+    )
+}
+
+/// Lists one page of a folder's entries, folders first. Hidden entries and,
+/// with `respect_ignore`, those matched by `.gitignore`/`.ignore` files can
+/// be left out. Entries that can't be read are listed with an `error`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn list_dir(
+    app_handle: tauri::AppHandle,
+    path: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<SortKey>,
+    descending: Option<bool>,
+    show_hidden: Option<bool>,
+    respect_ignore: Option<bool>,
+    git_status: Option<bool>,
+) -> Result<DirPage, String> {
+    let dir = authorize(&app_handle, &path, "read").await?;
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", path));
+    }
+
+    let respect_ignore = respect_ignore.unwrap_or(false);
+    let mut walker = WalkBuilder::new(&dir);
+    walker
+        .max_depth(Some(1))
+        .standard_filters(false)
+        .hidden(!show_hidden.unwrap_or(false))
+        .parents(respect_ignore)
+        .ignore(respect_ignore)
+        .git_ignore(respect_ignore)
+        .git_global(respect_ignore)
+        .git_exclude(respect_ignore);
+
+    let mut candidates = Vec::new();
+    let mut unreadable = Vec::new();
+    for entry in walker.build().skip(1) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = error_path(&e).unwrap_or(&dir).to_path_buf();
+                unreadable.push(FileMetadata::unreadable(path, e));
+                continue;
+            }
+        };
+        let metadata = entry.metadata().ok();
+        candidates.push(Candidate {
+            name: entry.file_name().to_string_lossy().to_string(),
+            is_dir: entry.file_type().is_some_and(|t| t.is_dir()),
+            modified: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            size: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+            path: entry.into_path(),
+        });
+    }
+
+    let sort = sort.unwrap_or_default();
+    let descending = descending.unwrap_or(false);
+    candidates.sort_by(|a, b| {
+        let ordering = compare(a, b, sort);
+        b.is_dir.cmp(&a.is_dir).then(if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        })
+    });
+
+    let total = candidates.len() + unreadable.len();
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let statuses = git_status
+        .unwrap_or(false)
+        .then(|| git::status_map(&dir))
+        .flatten();
+
+    let (listed_page, unreadable_page) =
+        page_ranges(candidates.len(), unreadable.len(), offset, limit);
+    let mut entries: Vec<FileMetadata> = candidates
+        .into_iter()
+        .skip(listed_page.start)
+        .take(listed_page.len())
+        .map(|candidate| {
+            let mut metadata = FileMetadata::read(candidate.path);
+            if let Some(statuses) = &statuses {
+                metadata.git_status =
+                    git::status_for(statuses, Path::new(&metadata.path), metadata.is_dir);
+            }
+            metadata
+        })
+        .collect();
+    // Errors that couldn't be tied to an entry come after everything else
+    entries.extend(
+        unreadable
+            .into_iter()
+            .skip(unreadable_page.start)
+            .take(unreadable_page.len()),
+    );
+
+    Ok(DirPage {
+        path,
+        has_more: offset + entries.len() < total,
+        entries,
+        total,
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file9"), Ordering::Greater);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case_and_orders_leading_zeros() {
+        assert_eq!(natural_cmp("Readme", "readme"), Ordering::Equal);
+        assert_eq!(natural_cmp("B", "a"), Ordering::Greater);
+        assert_eq!(natural_cmp("file007", "file7"), Ordering::Greater);
+        assert_eq!(natural_cmp("file007", "file8"), Ordering::Less);
+    }
+
+    #[test]
+    fn page_ranges_within_listed_entries() {
+        assert_eq!(page_ranges(10, 2, 0, 4), (0..4, 0..0));
+        assert_eq!(page_ranges(10, 2, 4, 4), (4..8, 0..0));
+    }
+
+    #[test]
+    fn page_ranges_continue_into_unreadable_entries() {
+        assert_eq!(page_ranges(10, 3, 8, 4), (8..10, 0..2));
+        assert_eq!(page_ranges(10, 3, 12, 4), (10..10, 2..3));
+        assert_eq!(page_ranges(10, 3, 20, 4), (10..10, 3..3));
+    }
+
+    #[test]
+    fn page_ranges_handle_huge_limits() {
+        assert_eq!(page_ranges(3, 1, 1, usize::MAX), (1..3, 0..1));
+    }
+
+    #[test]
+    fn error_path_finds_the_failed_entry() {
+        let io = || ignore::Error::Io(std::io::Error::other("denied"));
+        let with_path = ignore::Error::WithPath {
+            path: PathBuf::from("/dir/entry"),
+            err: Box::new(io()),
+        };
+        assert_eq!(error_path(&with_path), Some(Path::new("/dir/entry")));
+
+        let nested = ignore::Error::WithDepth {
+            depth: 1,
+            err: Box::new(with_path),
+        };
+        assert_eq!(error_path(&nested), Some(Path::new("/dir/entry")));
+        assert_eq!(error_path(&io()), None);
+    }
+}
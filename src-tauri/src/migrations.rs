@@ -0,0 +1,291 @@
+use duckdb::Connection;
+use std::fs;
+use std::path::Path;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Up-migrations for the chat database, applied in order. Never edit a
+/// migration once it has shipped; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../migrations/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "role_based_messages",
+        sql: include_str!("../migrations/0002_role_based_messages.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "session_management",
+        sql: include_str!("../migrations/0003_session_management.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "message_search_index",
+        sql: include_str!("../migrations/0004_message_search_index.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "conversation_branches",
+        sql: include_str!("../migrations/0005_conversation_branches.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "session_summaries",
+        sql: include_str!("../migrations/0006_session_summaries.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "response_latency",
+        sql: include_str!("../migrations/0007_response_latency.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "prompt_presets",
+        sql: include_str!("../migrations/0008_prompt_presets.sql"),
+    },
+];
+
+// Last migration whose result could be created without version tracking
+const UNTRACKED_VERSION: i64 = 2;
+// Migration that moves legacy exchange rows into the role-based schema
+const ROLE_BASED_VERSION: i64 = 2;
+// DuckDB's path for a database that only lives in memory
+const IN_MEMORY: &str = ":memory:";
+
+fn current_version(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Failed to read schema version: {}", e))
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM information_schema.columns WHERE table_name = ? AND column_name = ?",
+        [table, column],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|count| count > 0)
+    .map_err(|e| e.to_string())
+}
+
+fn record_version(conn: &Connection, migration: &Migration) -> duckdb::Result<usize> {
+    conn.execute(
+        "INSERT INTO schema_version (version, name) VALUES (?, ?)",
+        duckdb::params![migration.version, migration.name],
+    )
+}
+
+/// Brings the database at `db_path` up to the latest schema. The database
+/// file is backed up before any pending migration runs, and a failed
+/// migration is rolled back and reported together with the backup location.
+pub fn run_migrations(conn: &mut Connection, db_path: &Path) -> Result<(), String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );",
+    )
+    .map_err(|e| format!("Failed to create schema_version table: {}", e))?;
+
+    let mut version = current_version(conn)?;
+    let has_data = column_exists(conn, "messages", "id")?;
+
+    // Databases written before versioning already have the role-based schema
+    if version == 0 && column_exists(conn, "messages", "role")? {
+        println!(
+            "[migrations] Adopting untracked database at version {}",
+            UNTRACKED_VERSION
+        );
+        for migration in MIGRATIONS
+            .iter()
+            .take_while(|m| m.version <= UNTRACKED_VERSION)
+        {
+            record_version(conn, migration)
+                .map_err(|e| format!("Failed to record schema version: {}", e))?;
+        }
+        version = UNTRACKED_VERSION;
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > version).collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // An in-memory database has no file to back up
+    let backup = if has_data && db_path != Path::new(IN_MEMORY) {
+        Some(backup_database(conn, db_path, version)?)
+    } else {
+        None
+    };
+
+    for migration in pending {
+        if migration.version == ROLE_BASED_VERSION {
+            log_orphaned_messages(conn);
+        }
+        println!(
+            "[migrations] Applying migration {} ({})",
+            migration.version, migration.name
+        );
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Failed to start migration: {}", e))?;
+        let result = tx
+            .execute_batch(migration.sql)
+            .and_then(|_| record_version(&tx, migration))
+            .and_then(|_| tx.commit());
+
+        if let Err(e) = result {
+            let backup_note = backup
+                .as_ref()
+                .map(|path| format!(" A backup was saved to {}.", path.display()))
+                .unwrap_or_default();
+            return Err(format!(
+                "Migration {} ({}) failed: {}.{}",
+                migration.version, migration.name, e, backup_note
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Legacy rows without a session are moved into the "imported-legacy" session
+// by the role-based migration; report how many so the move isn't silent.
+fn log_orphaned_messages(conn: &Connection) {
+    match conn.query_row(
+        "SELECT COUNT(*) FROM messages WHERE session_id IS NULL",
+        [],
+        |row| row.get::<_, i64>(0),
+    ) {
+        Ok(0) => {}
+        Ok(count) => println!(
+            "[migrations] Moving {} legacy message(s) without a session into 'imported-legacy'",
+            count
+        ),
+        Err(e) => println!("[migrations] Failed to count legacy messages: {}", e),
+    }
+}
+
+fn backup_database(
+    conn: &Connection,
+    db_path: &Path,
+    version: i64,
+) -> Result<std::path::PathBuf, String> {
+    // Flush the write-ahead log so the copied file is complete
+    conn.execute_batch("CHECKPOINT;")
+        .map_err(|e| format!("Failed to checkpoint database: {}", e))?;
+
+    let backup_dir = db_path
+        .parent()
+        .ok_or_else(|| "Invalid database path".to_string())?
+        .join("backups");
+    fs::create_dir_all(&backup_dir)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    let backup_path = backup_dir.join(format!(
+        "chat_history-v{}-{}.duckdb",
+        version,
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::copy(db_path, &backup_path).map_err(|e| format!("Failed to back up database: {}", e))?;
+    println!(
+        "[migrations] Backed up database to {}",
+        backup_path.display()
+    );
+
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0].sql).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat_sessions (session_id, title) VALUES ('s1', 'One');
+             INSERT INTO messages (id, session_id, message, response)
+                 VALUES ('m1', 's1', 'hi', 'hello');
+             INSERT INTO messages (id, session_id, message, response)
+                 VALUES ('m2', NULL, 'orphan', 'reply');
+             INSERT INTO messages (id, session_id, message, response)
+                 VALUES ('m3', NULL, 'unanswered', NULL);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn messages(conn: &Connection) -> Vec<(String, String, String)> {
+        let mut stmt = conn
+            .prepare("SELECT session_id, role, content FROM messages ORDER BY content")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn legacy_messages_without_a_session_are_imported() {
+        let mut conn = legacy_database();
+        run_migrations(&mut conn, Path::new(IN_MEMORY)).unwrap();
+
+        let row = |session: &str, role: &str, content: &str| {
+            (session.to_string(), role.to_string(), content.to_string())
+        };
+        assert_eq!(
+            messages(&conn),
+            vec![
+                row("s1", "assistant", "hello"),
+                row("s1", "user", "hi"),
+                row("imported-legacy", "user", "orphan"),
+                row("imported-legacy", "assistant", "reply"),
+                row("imported-legacy", "user", "unanswered"),
+            ]
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM chat_sessions WHERE session_id = 'imported-legacy'"
+            ),
+            1
+        );
+        assert_eq!(
+            current_version(&conn).unwrap(),
+            MIGRATIONS.last().unwrap().version
+        );
+    }
+
+    #[test]
+    fn running_migrations_again_changes_nothing() {
+        let mut conn = legacy_database();
+        run_migrations(&mut conn, Path::new(IN_MEMORY)).unwrap();
+        let before = messages(&conn);
+        let versions = count(&conn, "SELECT COUNT(*) FROM schema_version");
+
+        run_migrations(&mut conn, Path::new(IN_MEMORY)).unwrap();
+        assert_eq!(messages(&conn), before);
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM schema_version"),
+            versions
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM chat_sessions"), 2);
+    }
+}
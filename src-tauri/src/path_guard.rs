@@ -0,0 +1,208 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_fs::FsExt;
+use tokio::sync::oneshot;
+
+/// The folders the file commands may touch: the project directory, opened
+/// workspaces, the models directory and anything the user confirmed.
+pub struct PathGuard {
+    roots: Mutex<Vec<PathBuf>>,
+}
+
+/// Resolves symlinks and `..` in the parents of an absolute `path`, so it
+/// can't point outside an allowed folder in disguise. The last component
+/// is kept as is, and parents that don't exist yet may not contain `..`.
+pub fn resolve(path: &Path) -> Result<PathBuf, String> {
+    if !path.is_absolute() {
+        return Err(format!("{} is not an absolute path", path.display()));
+    }
+
+    let invalid = || format!("Invalid path {}", path.display());
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name,
+        Some(Component::RootDir | Component::Prefix(_)) => return Ok(path.to_path_buf()),
+        _ => return Err(invalid()),
+    };
+    let parent = path.parent().ok_or_else(invalid)?;
+    let parent = match parent.canonicalize() {
+        Ok(parent) => parent,
+        Err(_) => resolve(parent)?,
+    };
+    Ok(parent.join(name))
+}
+
+impl PathGuard {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        PathGuard {
+            roots: Mutex::new(roots.iter().filter_map(|root| resolve(root).ok()).collect()),
+        }
+    }
+
+    pub(crate) fn allows(&self, path: &Path) -> bool {
+        self.roots
+            .lock()
+            .map(|roots| roots.iter().any(|root| path.starts_with(root)))
+            .unwrap_or(false)
+    }
+
+    /// Lets the file commands and the webview's fs plugin access `path` and
+    /// everything inside it.
+    pub fn allow(&self, app_handle: &tauri::AppHandle, path: &Path) -> Result<(), String> {
+        let resolved = resolve(path)?;
+        app_handle
+            .fs_scope()
+            .allow_directory(&resolved, true)
+            .map_err(|e| format!("Failed to allow access to {}: {}", path.display(), e))?;
+        let mut roots = self.roots.lock().map_err(|e| e.to_string())?;
+        if !roots.iter().any(|root| resolved.starts_with(root)) {
+            roots.push(resolved);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a file command may access `path`. Paths outside the allowed
+/// folders are put to the user in a native confirmation dialog, so the
+/// webview can't answer it for them. A symlink is also checked by its
+/// target.
+pub async fn authorize(
+    app_handle: &tauri::AppHandle,
+    path: &str,
+    operation: &str,
+) -> Result<PathBuf, String> {
+    let requested = PathBuf::from(path);
+    let resolved = resolve(&requested)?;
+    let target = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
+
+    let guard = app_handle.state::<PathGuard>();
+    if guard.allows(&resolved) && guard.allows(&target) {
+        return Ok(requested);
+    }
+
+    println!(
+        "[authorize] Asking for {} access outside the workspace: {}",
+        operation, path
+    );
+    let (sender, receiver) = oneshot::channel();
+    app_handle
+        .dialog()
+        .message(format!(
+            "Allow {} access to {}? It is outside the open workspace.",
+            operation, path
+        ))
+        .title("Access outside the workspace")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Deny".to_string(),
+        ))
+        .show(move |allowed| {
+            let _ = sender.send(allowed);
+        });
+    if !receiver.await.unwrap_or(false) {
+        return Err(format!("Access to {} was denied", path));
+    }
+
+    guard.allow(app_handle, &resolved)?;
+    if target != resolved {
+        guard.allow(app_handle, &target)?;
+    }
+    Ok(requested)
+}
+
+/// Lets the user pick a workspace folder with the native dialog and adds it
+/// to the allowed folders. The folder never comes from the webview, so it
+/// can't widen the guard by itself. Returns None if the dialog was closed.
+#[tauri::command]
+pub async fn open_workspace(app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
+    let (sender, receiver) = oneshot::channel();
+    app_handle.dialog().file().pick_folder(move |folder| {
+        let _ = sender.send(folder);
+    });
+    let Some(folder) = receiver
+        .await
+        .map_err(|_| "Folder dialog closed unexpectedly".to_string())?
+    else {
+        return Ok(None);
+    };
+
+    let path = folder
+        .into_path()
+        .map_err(|e| format!("Invalid folder: {}", e))?;
+    app_handle.state::<PathGuard>().allow(&app_handle, &path)?;
+    println!("[open_workspace] Allowed access to {}", path.display());
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn resolve_keeps_the_root_path() {
+        assert_eq!(resolve(Path::new("/")).unwrap(), PathBuf::from("/"));
+        assert!(resolve(Path::new("relative/file")).is_err());
+    }
+
+    #[test]
+    fn resolve_rejects_parent_dirs_inside_a_missing_parent() {
+        let (_dir, root) = workspace();
+        let sneaky = root.join("missing").join("..").join("..").join("etc");
+        assert!(resolve(&sneaky.join("passwd")).is_err());
+        assert!(resolve(&root.join("missing").join("..")).is_err());
+    }
+
+    #[test]
+    fn resolve_allows_missing_parents_without_parent_dirs() {
+        let (_dir, root) = workspace();
+        let path = root.join("new").join("deeper").join("file.txt");
+        assert_eq!(resolve(&path).unwrap(), path);
+        assert_eq!(
+            resolve(&root.join("..").join("file.txt")).unwrap(),
+            root.parent().unwrap().join("file.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_parents_are_checked_by_their_target() {
+        let (_dir, root) = workspace();
+        let (_other, outside) = workspace();
+        let workspace = root.join("workspace");
+        std::fs::create_dir(&workspace).unwrap();
+        std::os::unix::fs::symlink(&outside, workspace.join("link")).unwrap();
+
+        let resolved = resolve(&workspace.join("link").join("secret.txt")).unwrap();
+        assert_eq!(resolved, outside.join("secret.txt"));
+
+        let guard = PathGuard::new(vec![workspace.clone()]);
+        assert!(!guard.allows(&resolved));
+        assert!(guard.allows(&resolve(&workspace.join("file.txt")).unwrap()));
+    }
+
+    #[test]
+    fn allows_matches_whole_components() {
+        let (_dir, root) = workspace();
+        let guard = PathGuard::new(vec![root.join("project")]);
+        assert!(guard.allows(&root.join("project")));
+        assert!(guard.allows(&root.join("project").join("src").join("main.rs")));
+        assert!(!guard.allows(&root.join("project-other")));
+        assert!(!guard.allows(&root));
+    }
+
+    #[test]
+    fn root_path_allows_everything() {
+        let guard = PathGuard::new(vec![PathBuf::from("/")]);
+        assert!(guard.allows(Path::new("/")));
+        assert!(guard.allows(Path::new("/etc/passwd")));
+        assert!(!PathGuard::new(Vec::new()).allows(Path::new("/")));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::config::{get_default_project_directory, stored_project_directory};
+use crate::db::{Database, PromptPreset};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+use tauri::Manager;
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*(\w+)\s*\}\}").expect("valid placeholder regex"));
+
+/// Values for the `{{variable}}` placeholders in a preset.
+fn template_variables(
+    app_handle: &tauri::AppHandle,
+    open_file: Option<&str>,
+    language: Option<&str>,
+) -> HashMap<&'static str, String> {
+    // Rendering a prompt shouldn't write the config, so the default is
+    // used as is while none is saved
+    let project_dir = stored_project_directory(app_handle)
+        .map(|dir| dir.unwrap_or_else(get_default_project_directory))
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let language = language
+        .map(|language| language.to_string())
+        .or_else(|| open_file.and_then(language_for_file))
+        .unwrap_or_default();
+
+    HashMap::from([
+        ("project_dir", project_dir),
+        ("open_file", open_file.unwrap_or_default().to_string()),
+        ("language", language),
+    ])
+}
+
+pub(crate) fn language_for_file(path: &str) -> Option<String> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "Rust",
+        "ts" | "tsx" => "TypeScript",
+        "js" | "jsx" | "mjs" => "JavaScript",
+        "py" => "Python",
+        "go" => "Go",
+        "java" => "Java",
+        "c" | "h" => "C",
+        "cpp" | "cc" | "hpp" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "sql" => "SQL",
+        "sh" | "bash" | "zsh" => "Shell",
+        "html" => "HTML",
+        "css" => "CSS",
+        "json" => "JSON",
+        "toml" => "TOML",
+        "yaml" | "yml" => "YAML",
+        "md" => "Markdown",
+        _ => return None,
+    };
+    Some(language.to_string())
+}
+
+/// Replaces known `{{variable}}` placeholders, leaving unknown ones as
+/// written so typos stay visible.
+fn render_template(template: &str, variables: &HashMap<&'static str, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &Captures| {
+            variables
+                .get(&caps[1])
+                .cloned()
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The session's preset with its variables filled in, if it has one.
+pub fn session_system_prompt(
+    app_handle: &tauri::AppHandle,
+    session_id: &str,
+    open_file: Option<&str>,
+    language: Option<&str>,
+) -> Result<Option<String>, String> {
+    let db = app_handle.state::<Database>();
+    let Some(preset) = db.get_session_preset(session_id)? else {
+        return Ok(None);
+    };
+    let variables = template_variables(app_handle, open_file, language);
+    Ok(Some(render_template(&preset.content, &variables)))
+}
+
+#[tauri::command]
+pub fn list_prompt_presets(app_handle: tauri::AppHandle) -> Result<Vec<PromptPreset>, String> {
+    app_handle.state::<Database>().list_presets()
+}
+
+#[tauri::command]
+pub fn create_prompt_preset(
+    app_handle: tauri::AppHandle,
+    name: String,
+    content: String,
+) -> Result<PromptPreset, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+
+    let db = app_handle.state::<Database>();
+    let id = uuid::Uuid::new_v4().to_string();
+    db.save_preset(&id, name, &content)?;
+    db.get_preset(&id)
+}
+
+#[tauri::command]
+pub fn update_prompt_preset(
+    app_handle: tauri::AppHandle,
+    id: String,
+    name: Option<String>,
+    content: Option<String>,
+) -> Result<PromptPreset, String> {
+    let db = app_handle.state::<Database>();
+    let preset = db.get_preset(&id)?;
+    let name = name.as_deref().map(str::trim).unwrap_or(&preset.name);
+    if name.is_empty() {
+        return Err("Preset name cannot be empty".to_string());
+    }
+
+    db.save_preset(&id, name, content.as_deref().unwrap_or(&preset.content))?;
+    db.get_preset(&id)
+}
+
+#[tauri::command]
+pub fn delete_prompt_preset(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    app_handle.state::<Database>().delete_preset(&id)
+}
+
+/// Attaches a preset to a session, or detaches it when `preset_id` is None.
+#[tauri::command]
+pub fn set_session_preset(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    preset_id: Option<String>,
+) -> Result<(), String> {
+    let db = app_handle.state::<Database>();
+    if let Some(preset_id) = &preset_id {
+        db.get_preset(preset_id)?;
+    }
+    db.set_session_preset(&session_id, preset_id.as_deref())
+}
+
+/// Previews the system prompt a session would send with the given editor
+/// state.
+#[tauri::command]
+pub fn render_session_prompt(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    open_file: Option<String>,
+    language: Option<String>,
+) -> Result<Option<String>, String> {
+    session_system_prompt(
+        &app_handle,
+        &session_id,
+        open_file.as_deref(),
+        language.as_deref(),
+    )
+}
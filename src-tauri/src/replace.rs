@@ -0,0 +1,500 @@
+use crate::path_guard::authorize;
+use crate::search::{build_matcher, read_text, workspace_walker};
+use crate::text_file::write_target;
+use regex::{NoExpand, Regex};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+// Previews and undo records are dropped once this old, or when too many
+// are kept, since each one holds the full contents of every file it touches
+const PREVIEW_TTL: Duration = Duration::from_secs(30 * 60);
+const MAX_KEPT_REPLACES: usize = 8;
+
+#[derive(Default)]
+pub struct ReplaceState {
+    /// Previews waiting to be applied, by replace id
+    pending: Mutex<HashMap<String, Kept<PendingFile>>>,
+    /// Replacements that can still be undone, by undo id
+    applied: Mutex<HashMap<String, Kept<AppliedFile>>>,
+}
+
+/// Files held for a later apply or undo.
+struct Kept<T> {
+    created: Instant,
+    files: Vec<T>,
+}
+
+impl<T> Kept<T> {
+    fn new(files: Vec<T>) -> Self {
+        Kept {
+            created: Instant::now(),
+            files,
+        }
+    }
+}
+
+struct PendingFile {
+    path: PathBuf,
+    original: String,
+    hunks: Vec<ReplaceHunk>,
+}
+
+struct AppliedFile {
+    path: PathBuf,
+    before: String,
+    after: String,
+}
+
+/// One changed line. Hunks are selected for applying by their line number.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaceHunk {
+    /// 1-based line number
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+    pub replacements: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReplacePreview {
+    pub path: String,
+    pub hunks: Vec<ReplaceHunk>,
+    /// The file's changes as a unified diff
+    pub diff: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplacePreview {
+    pub replace_id: String,
+    pub files: Vec<FileReplacePreview>,
+    pub replacements: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplaceResult {
+    pub undo_id: String,
+    pub files_changed: usize,
+    pub replacements: usize,
+}
+
+/// Splits a line into its text and its line ending.
+fn split_ending(line: &str) -> (&str, &str) {
+    let body = line
+        .strip_suffix("\r\n")
+        .or_else(|| line.strip_suffix('\n'))
+        .unwrap_or(line);
+    (body, &line[body.len()..])
+}
+
+fn preview_file(text: &str, matcher: &Regex, replacement: &str, literal: bool) -> Vec<ReplaceHunk> {
+    let mut hunks = Vec::new();
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let (body, _) = split_ending(line);
+        let replacements = matcher.find_iter(body).count();
+        if replacements == 0 {
+            continue;
+        }
+        let after = if literal {
+            matcher.replace_all(body, NoExpand(replacement))
+        } else {
+            matcher.replace_all(body, replacement)
+        };
+        if after != body {
+            hunks.push(ReplaceHunk {
+                line: index + 1,
+                before: body.to_string(),
+                after: after.into_owned(),
+                replacements,
+            });
+        }
+    }
+    hunks
+}
+
+fn unified_diff(path: &str, hunks: &[ReplaceHunk]) -> String {
+    let mut diff = format!("--- a/{0}\n+++ b/{0}\n", path);
+    for hunk in hunks {
+        diff.push_str(&format!(
+            "@@ -{0},1 +{0},1 @@\n-{1}\n+{2}\n",
+            hunk.line, hunk.before, hunk.after
+        ));
+    }
+    diff
+}
+
+fn apply_hunks(original: &str, hunks: &[&ReplaceHunk]) -> String {
+    let by_line: HashMap<usize, &str> = hunks
+        .iter()
+        .map(|hunk| (hunk.line, hunk.after.as_str()))
+        .collect();
+    original
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(index, line)| match by_line.get(&(index + 1)) {
+            Some(after) => format!("{}{}", after, split_ending(line).1),
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+/// Replaces the contents of several files so that either all of them end up
+/// with their new contents or, if a write fails, the ones already replaced
+/// are put back. Each entry is `(path, current, new)`, and symlinks are
+/// written through to their targets.
+fn replace_files(files: &[(&Path, &str, &str)]) -> Result<(), String> {
+    let mut staged = Vec::new();
+    for (path, _, contents) in files {
+        let target = write_target(path)?;
+        let dir = target.parent().unwrap_or(Path::new("."));
+        let mut temp = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|e| format!("Failed to create temp file for {}: {}", path.display(), e))?;
+        temp.write_all(contents.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            let _ = fs::set_permissions(temp.path(), metadata.permissions());
+        }
+        staged.push((target, temp));
+    }
+
+    let mut replaced: Vec<(PathBuf, &str)> = Vec::new();
+    for (index, (target, temp)) in staged.into_iter().enumerate() {
+        let (path, current, _) = files[index];
+        if let Err(e) = temp.persist(&target) {
+            let unrestored: Vec<String> = replaced
+                .iter()
+                .filter(|(target, current)| fs::write(target, current).is_err())
+                .map(|(target, _)| target.display().to_string())
+                .collect();
+            let mut message = format!("Failed to replace {}: {}", path.display(), e);
+            if !unrestored.is_empty() {
+                message.push_str(&format!(
+                    ". These files couldn't be restored and keep their new contents: {}",
+                    unrestored.join(", ")
+                ));
+            }
+            return Err(message);
+        }
+        replaced.push((target, current));
+    }
+    Ok(())
+}
+
+/// Drops expired previews or undo records and, if the limit is reached, the
+/// oldest ones so there is room for another.
+fn prune_previews<T>(kept: &mut HashMap<String, Kept<T>>) {
+    kept.retain(|_, entry| entry.created.elapsed() < PREVIEW_TTL);
+    while kept.len() >= MAX_KEPT_REPLACES {
+        let Some(oldest) = kept
+            .iter()
+            .min_by_key(|(_, entry)| entry.created)
+            .map(|(id, _)| id.clone())
+        else {
+            break;
+        };
+        kept.remove(&oldest);
+    }
+}
+
+/// Finds every change replacing `query` with `replacement` would make under
+/// `root` and returns it as a preview; nothing is written until
+/// `apply_workspace_replace`. In regex mode the replacement may refer to
+/// capture groups as `$1` or `${name}`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn replace_in_workspace(
+    app_handle: tauri::AppHandle,
+    root: String,
+    query: String,
+    replacement: String,
+    regex: Option<bool>,
+    case_sensitive: Option<bool>,
+    whole_word: Option<bool>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<ReplacePreview, String> {
+    let root = authorize(&app_handle, &root, "write").await?;
+    let literal = !regex.unwrap_or(false);
+    let matcher = build_matcher(
+        &query,
+        !literal,
+        case_sensitive.unwrap_or(false),
+        whole_word.unwrap_or(false),
+    )?;
+    let walker = workspace_walker(
+        &root,
+        &include.unwrap_or_default(),
+        &exclude.unwrap_or_default(),
+    )?;
+
+    let pending = tauri::async_runtime::spawn_blocking(move || {
+        let mut pending = Vec::new();
+        for entry in walker.build().flatten() {
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let Some(text) = read_text(entry.path()) else {
+                continue;
+            };
+            let hunks = preview_file(&text, &matcher, &replacement, literal);
+            if !hunks.is_empty() {
+                pending.push(PendingFile {
+                    path: entry.into_path(),
+                    original: text,
+                    hunks,
+                });
+            }
+        }
+        pending.sort_by(|a, b| a.path.cmp(&b.path));
+        pending
+    })
+    .await
+    .map_err(|e| format!("Replace preview failed: {}", e))?;
+
+    let files: Vec<FileReplacePreview> = pending
+        .iter()
+        .map(|file| {
+            let path = file.path.to_string_lossy().to_string();
+            let relative = file
+                .path
+                .strip_prefix(&root)
+                .unwrap_or(&file.path)
+                .to_string_lossy()
+                .to_string();
+            FileReplacePreview {
+                diff: unified_diff(&relative, &file.hunks),
+                hunks: file.hunks.clone(),
+                path,
+            }
+        })
+        .collect();
+    let replacements = files
+        .iter()
+        .flat_map(|file| &file.hunks)
+        .map(|hunk| hunk.replacements)
+        .sum();
+
+    let replace_id = uuid::Uuid::new_v4().to_string();
+    let state = app_handle.state::<ReplaceState>();
+    let mut previews = state.pending.lock().map_err(|e| e.to_string())?;
+    prune_previews(&mut previews);
+    previews.insert(replace_id.clone(), Kept::new(pending));
+
+    Ok(ReplacePreview {
+        replace_id,
+        files,
+        replacements,
+    })
+}
+
+/// Applies a preview. `selected` maps file paths to the line numbers of the
+/// hunks to apply; files missing from it are left alone. Without a
+/// selection every hunk is applied. Fails without writing anything if a
+/// file changed since the preview.
+#[tauri::command]
+pub fn apply_workspace_replace(
+    app_handle: tauri::AppHandle,
+    replace_id: String,
+    selected: Option<HashMap<String, Vec<usize>>>,
+) -> Result<ReplaceResult, String> {
+    let state = app_handle.state::<ReplaceState>();
+    let mut pending = state.pending.lock().map_err(|e| e.to_string())?;
+    prune_previews(&mut pending);
+    let files = &pending
+        .get(&replace_id)
+        .ok_or_else(|| format!("Replace preview {} not found or expired", replace_id))?
+        .files;
+
+    let mut changes = Vec::new();
+    let mut replacements = 0;
+    for file in files {
+        let path = file.path.to_string_lossy().to_string();
+        let hunks: Vec<&ReplaceHunk> = match &selected {
+            None => file.hunks.iter().collect(),
+            Some(selected) => match selected.get(&path) {
+                Some(lines) => file
+                    .hunks
+                    .iter()
+                    .filter(|hunk| lines.contains(&hunk.line))
+                    .collect(),
+                None => continue,
+            },
+        };
+        if hunks.is_empty() {
+            continue;
+        }
+
+        let current = fs::read_to_string(&file.path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        if current != file.original {
+            return Err(format!(
+                "{} changed since the preview, search again before replacing",
+                path
+            ));
+        }
+        replacements += hunks.iter().map(|hunk| hunk.replacements).sum::<usize>();
+        changes.push(AppliedFile {
+            path: file.path.clone(),
+            after: apply_hunks(&file.original, &hunks),
+            before: file.original.clone(),
+        });
+    }
+
+    let writes: Vec<(&Path, &str, &str)> = changes
+        .iter()
+        .map(|change| {
+            (
+                change.path.as_path(),
+                change.before.as_str(),
+                change.after.as_str(),
+            )
+        })
+        .collect();
+    replace_files(&writes)?;
+    pending.remove(&replace_id);
+
+    let undo_id = uuid::Uuid::new_v4().to_string();
+    let files_changed = changes.len();
+    let mut applied = state.applied.lock().map_err(|e| e.to_string())?;
+    prune_previews(&mut applied);
+    applied.insert(undo_id.clone(), Kept::new(changes));
+
+    println!(
+        "[apply_workspace_replace] {} replacements in {} files",
+        replacements, files_changed
+    );
+    Ok(ReplaceResult {
+        undo_id,
+        files_changed,
+        replacements,
+    })
+}
+
+/// Forgets a preview that won't be applied. Returns false if it had already
+/// been applied, discarded or expired.
+#[tauri::command]
+pub fn discard_workspace_replace(
+    app_handle: tauri::AppHandle,
+    replace_id: String,
+) -> Result<bool, String> {
+    let state = app_handle.state::<ReplaceState>();
+    let mut pending = state.pending.lock().map_err(|e| e.to_string())?;
+    Ok(pending.remove(&replace_id).is_some())
+}
+
+/// Restores the files changed by an applied replacement, as long as none of
+/// them were edited since.
+#[tauri::command]
+pub fn undo_workspace_replace(
+    app_handle: tauri::AppHandle,
+    undo_id: String,
+) -> Result<usize, String> {
+    let state = app_handle.state::<ReplaceState>();
+    let mut applied = state.applied.lock().map_err(|e| e.to_string())?;
+    applied.retain(|_, entry| entry.created.elapsed() < PREVIEW_TTL);
+    let changes = &applied
+        .get(&undo_id)
+        .ok_or_else(|| format!("Nothing to undo for {}, or it expired", undo_id))?
+        .files;
+
+    for change in changes {
+        let current = fs::read_to_string(&change.path).unwrap_or_default();
+        if current != change.after {
+            return Err(format!(
+                "{} was modified after the replacement and can't be restored",
+                change.path.display()
+            ));
+        }
+    }
+
+    let writes: Vec<(&Path, &str, &str)> = changes
+        .iter()
+        .map(|change| {
+            (
+                change.path.as_path(),
+                change.after.as_str(),
+                change.before.as_str(),
+            )
+        })
+        .collect();
+    replace_files(&writes)?;
+
+    let restored = changes.len();
+    applied.remove(&undo_id);
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_hunks_keeps_line_endings() {
+        let text = "one foo\r\ntwo\nthree foo";
+        let matcher = build_matcher("foo", false, false, false).unwrap();
+        let hunks = preview_file(text, &matcher, "bar", true);
+        assert_eq!(hunks.len(), 2);
+        let selected: Vec<&ReplaceHunk> = hunks.iter().collect();
+        assert_eq!(apply_hunks(text, &selected), "one bar\r\ntwo\nthree bar");
+        assert_eq!(
+            apply_hunks(text, &selected[1..]),
+            "one foo\r\ntwo\nthree bar"
+        );
+    }
+
+    #[test]
+    fn replace_files_puts_back_earlier_files_when_one_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        let folder = dir.path().join("folder");
+        fs::write(&file, "old").unwrap();
+        fs::create_dir(&folder).unwrap();
+
+        let error = replace_files(&[(&file, "old", "new"), (&folder, "", "text")]).unwrap_err();
+        assert!(error.starts_with("Failed to replace"), "{}", error);
+        assert!(!error.contains("couldn't be restored"), "{}", error);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_files_writes_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("real.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&file, "old").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        replace_files(&[(&link, "old", "new")]).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+    }
+
+    #[test]
+    fn prune_previews_makes_room_by_dropping_the_oldest() {
+        let mut kept: HashMap<String, Kept<()>> = HashMap::new();
+        let now = Instant::now();
+        for index in 0..MAX_KEPT_REPLACES {
+            kept.insert(
+                index.to_string(),
+                Kept {
+                    created: now + Duration::from_millis(index as u64),
+                    files: Vec::new(),
+                },
+            );
+        }
+
+        prune_previews(&mut kept);
+        assert_eq!(kept.len(), MAX_KEPT_REPLACES - 1);
+        assert!(!kept.contains_key("0"));
+        assert!(kept.contains_key(&(MAX_KEPT_REPLACES - 1).to_string()));
+    }
+}
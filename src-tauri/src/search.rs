@@ -0,0 +1,357 @@
+use crate::path_guard::authorize;
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkState};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+
+const DEFAULT_MAX_RESULTS: usize = 2000;
+// Larger files are assumed to be data rather than source and are skipped
+const MAX_SEARCH_FILE_SIZE: u64 = 10 * 1024 * 1024;
+// A NUL byte in this many leading bytes marks a file as binary
+pub(crate) const BINARY_SNIFF_LEN: usize = 8192;
+// Characters of context kept before the first match in a preview
+const PREVIEW_CONTEXT: usize = 40;
+const PREVIEW_LENGTH: usize = 200;
+
+#[derive(Default)]
+pub struct SearchState {
+    /// Cancellation flags of the searches currently running, by search id
+    pub searches: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineMatch {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based character column of the first match on the line
+    pub column: usize,
+    pub preview: String,
+    /// Character ranges within `preview` that matched
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceSearchResult {
+    pub search_id: String,
+    pub path: String,
+    pub matches: Vec<LineMatch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceSearchSummary {
+    pub search_id: String,
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub matches: usize,
+    /// Whether the search stopped at `max_results`
+    pub truncated: bool,
+    pub cancelled: bool,
+}
+
+pub(crate) fn build_matcher(
+    query: &str,
+    regex: bool,
+    case_sensitive: bool,
+    whole_word: bool,
+) -> Result<Regex, String> {
+    if query.is_empty() {
+        return Err("Search query cannot be empty".to_string());
+    }
+
+    let pattern = if regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    let pattern = if whole_word {
+        format!(r"\b(?:{})\b", pattern)
+    } else {
+        pattern
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .multi_line(true)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))
+}
+
+/// Walks the files under `root` that aren't ignored by `.gitignore`/`.ignore`
+/// files, limited by `include` globs and with `exclude` globs left out.
+pub(crate) fn workspace_walker(
+    root: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<WalkBuilder, String> {
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in include {
+        overrides
+            .add(glob)
+            .map_err(|e| format!("Invalid include pattern {}: {}", glob, e))?;
+    }
+    for glob in exclude {
+        overrides
+            .add(&format!("!{}", glob))
+            .map_err(|e| format!("Invalid exclude pattern {}: {}", glob, e))?;
+    }
+    let overrides = overrides
+        .build()
+        .map_err(|e| format!("Invalid file patterns: {}", e))?;
+
+    let mut walker = WalkBuilder::new(root);
+    walker.overrides(overrides);
+    Ok(walker)
+}
+
+/// Reads a file as text, or returns None for binary and oversized files.
+pub(crate) fn read_text(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.len() > MAX_SEARCH_FILE_SIZE {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn char_offset(text: &str, byte: usize) -> usize {
+    text[..byte].chars().count()
+}
+
+fn search_text(text: &str, matcher: &Regex) -> Vec<LineMatch> {
+    let mut matches = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let ranges: Vec<(usize, usize)> = matcher
+            .find_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| (char_offset(line, m.start()), char_offset(line, m.end())))
+            .collect();
+        let Some(&(first, _)) = ranges.first() else {
+            continue;
+        };
+
+        let chars: Vec<char> = line.chars().collect();
+        let start = first.saturating_sub(PREVIEW_CONTEXT);
+        let end = (start + PREVIEW_LENGTH).min(chars.len());
+        matches.push(LineMatch {
+            line: index + 1,
+            column: first + 1,
+            preview: chars[start..end].iter().collect(),
+            highlights: ranges
+                .into_iter()
+                .filter(|&(s, e)| s >= start && e <= end)
+                .map(|(s, e)| (s - start, e - start))
+                .collect(),
+        });
+    }
+    matches
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_search(
+    app_handle: &tauri::AppHandle,
+    search_id: &str,
+    root: &Path,
+    matcher: &Regex,
+    include: &[String],
+    exclude: &[String],
+    max_results: usize,
+    cancelled: &AtomicBool,
+) -> Result<WorkspaceSearchSummary, String> {
+    let walker = workspace_walker(root, include, exclude)?.build_parallel();
+    let files_searched = &AtomicUsize::new(0);
+    let files_matched = &AtomicUsize::new(0);
+    let total = &AtomicUsize::new(0);
+    let truncated = &AtomicBool::new(false);
+
+    walker.run(|| {
+        Box::new(move |entry| {
+            if cancelled.load(Ordering::Relaxed) || truncated.load(Ordering::Relaxed) {
+                return WalkState::Quit;
+            }
+            let Ok(entry) = entry else {
+                return WalkState::Continue;
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                return WalkState::Continue;
+            }
+
+            files_searched.fetch_add(1, Ordering::Relaxed);
+            let Some(text) = read_text(entry.path()) else {
+                return WalkState::Continue;
+            };
+            let mut matches = search_text(&text, matcher);
+            if matches.is_empty() {
+                return WalkState::Continue;
+            }
+
+            let before = total.fetch_add(matches.len(), Ordering::SeqCst);
+            if before >= max_results {
+                truncated.store(true, Ordering::Relaxed);
+                return WalkState::Quit;
+            }
+            if before + matches.len() > max_results {
+                matches.truncate(max_results - before);
+                truncated.store(true, Ordering::Relaxed);
+            }
+
+            files_matched.fetch_add(1, Ordering::Relaxed);
+            let _ = app_handle.emit(
+                "workspace_search_result",
+                WorkspaceSearchResult {
+                    search_id: search_id.to_string(),
+                    path: entry.path().to_string_lossy().to_string(),
+                    matches,
+                },
+            );
+            WalkState::Continue
+        })
+    });
+
+    Ok(WorkspaceSearchSummary {
+        search_id: search_id.to_string(),
+        files_searched: files_searched.load(Ordering::Relaxed),
+        files_matched: files_matched.load(Ordering::Relaxed),
+        matches: total.load(Ordering::Relaxed).min(max_results),
+        truncated: truncated.load(Ordering::Relaxed),
+        cancelled: cancelled.load(Ordering::Relaxed),
+    })
+}
+
+/// Searches the files under `root` in parallel, skipping ignored and binary
+/// files. Matches are streamed per file as `workspace_search_result` events;
+/// the returned summary marks the end of the search.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn search_workspace(
+    app_handle: tauri::AppHandle,
+    search_id: String,
+    root: String,
+    query: String,
+    regex: Option<bool>,
+    case_sensitive: Option<bool>,
+    whole_word: Option<bool>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    max_results: Option<usize>,
+) -> Result<WorkspaceSearchSummary, String> {
+    let root = authorize(&app_handle, &root, "read").await?;
+    let matcher = build_matcher(
+        &query,
+        regex.unwrap_or(false),
+        case_sensitive.unwrap_or(false),
+        whole_word.unwrap_or(false),
+    )?;
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let state = app_handle.state::<SearchState>();
+        let mut searches = state.searches.lock().map_err(|e| e.to_string())?;
+        if let Some(previous) = searches.insert(search_id.clone(), cancelled.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let handle = app_handle.clone();
+    let id = search_id.clone();
+    let flag = cancelled.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        run_search(
+            &handle,
+            &id,
+            &root,
+            &matcher,
+            &include.unwrap_or_default(),
+            &exclude.unwrap_or_default(),
+            max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+            &flag,
+        )
+    })
+    .await
+    .map_err(|e| format!("Search failed: {}", e))?;
+
+    let state = app_handle.state::<SearchState>();
+    let mut searches = state.searches.lock().map_err(|e| e.to_string())?;
+    if searches
+        .get(&search_id)
+        .is_some_and(|flag| Arc::ptr_eq(flag, &cancelled))
+    {
+        searches.remove(&search_id);
+    }
+
+    let summary = result?;
+    println!(
+        "[search_workspace] {} matches in {} of {} files",
+        summary.matches, summary.files_matched, summary.files_searched
+    );
+    Ok(summary)
+}
+
+#[tauri::command]
+pub fn cancel_workspace_search(
+    app_handle: tauri::AppHandle,
+    search_id: String,
+) -> Result<(), String> {
+    let state = app_handle.state::<SearchState>();
+    let searches = state.searches.lock().map_err(|e| e.to_string())?;
+    match searches.get(&search_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        None => Err(format!("No search with id {}", search_id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_queries_are_escaped() {
+        let matcher = build_matcher("a.b(", false, true, false).unwrap();
+        assert!(matcher.is_match("call a.b(1)"));
+        assert!(!matcher.is_match("axb("));
+    }
+
+    #[test]
+    fn regex_queries_are_used_as_is() {
+        let matcher = build_matcher(r"fn \w+\(", true, true, false).unwrap();
+        assert!(matcher.is_match("pub fn build_matcher("));
+        assert!(build_matcher("(unclosed", true, true, false).is_err());
+    }
+
+    #[test]
+    fn case_sensitivity_is_honoured() {
+        assert!(build_matcher("todo", false, false, false)
+            .unwrap()
+            .is_match("// TODO"));
+        assert!(!build_matcher("todo", false, true, false)
+            .unwrap()
+            .is_match("// TODO"));
+    }
+
+    #[test]
+    fn whole_word_wraps_alternations() {
+        let matcher = build_matcher("cat|dog", true, true, true).unwrap();
+        assert!(matcher.is_match("hot dog"));
+        assert!(!matcher.is_match("concatenate"));
+        assert!(!matcher.is_match("dogma"));
+    }
+
+    #[test]
+    fn empty_query_is_rejected() {
+        assert!(build_matcher("", false, false, false).is_err());
+    }
+}
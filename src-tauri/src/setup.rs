@@ -1,4 +1,15 @@
-use crate::{config::get_project_directory, db::Database, terminal::PtyState};
+use crate::{
+    config::get_project_directory,
+    context::{ContextModel, ContextState},
+    db::Database,
+    file_history::FileHistory,
+    file_jobs::FileJobs,
+    finder::FinderState,
+    path_guard::PathGuard,
+    replace::ReplaceState,
+    search::SearchState,
+    terminal::{recordings_dir, PtyState},
+};
 use std::collections::HashMap;
 use std::sync::Mutex;
 use tauri::Manager;
@@ -6,9 +17,21 @@ use tauri_plugin_fs::FsExt;
 
 pub fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     let handle = app.handle();
-    let db = Database::init(&handle).expect("Failed to initialize database");
+    let db = Database::init(&handle).unwrap_or_else(|e| {
+        println!("[setup] Chat database unavailable: {}", e);
+        Database::unavailable(e)
+    });
     app.manage(db);
 
+    // Token counting falls back to estimates until a model is selected
+    app.manage(ContextState {
+        model: Mutex::new(ContextModel::default()),
+    });
+
+    app.manage(FinderState::default());
+    app.manage(SearchState::default());
+    app.manage(ReplaceState::default());
+
     // Initialize terminal state
     app.manage(PtyState {
         ptys: Mutex::new(HashMap::new()),
@@ -25,48 +48,29 @@ pub fn setup_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>>
         std::fs::create_dir_all(&models_dir).expect("Failed to create models directory");
     }
 
-    get_project_directory(&handle)?;
+    // Deleted files are kept here so file operations can be undone
+    app.manage(FileHistory::new(app_data_dir.join("trash")));
+    app.manage(FileJobs::default());
 
-    let fs_scope = app.fs_scope();
+    let project_dir = get_project_directory(&handle)?;
 
-    // Allow access to models directory
-    fs_scope
-        .allow_directory(&models_dir, true)
-        .expect("Failed to allow models directory access");
+    // File commands and the webview may only touch the project directory,
+    // the models and recordings directories and workspaces the user opens;
+    // anything else needs the user's confirmation
+    #[allow(unused_mut)]
+    let mut roots = vec![project_dir, models_dir, recordings_dir(&handle)?];
 
     // Allow access to source directory in development
     #[cfg(debug_assertions)]
-    {
-        let current_dir = std::env::current_dir().expect("Failed to get current directory");
-        fs_scope
-            .allow_directory(&current_dir, true)
-            .expect("Failed to allow source directory access");
-    }
-
-    // Allow full filesystem access
-    {
-        let fs_scope = app.fs_scope();
-
-        #[cfg(unix)]
-        {
-            fs_scope
-                .allow_directory("/", true)
-                .expect("Failed to allow root directory access");
-        }
+    roots.push(std::env::current_dir().expect("Failed to get current directory"));
 
-        #[cfg(windows)]
-        {
-            // Allow all Windows drives
-            for drive in 'A'..='Z' {
-                let drive_path = format!("{}:\\", drive);
-                if std::path::Path::new(&drive_path).exists() {
-                    fs_scope
-                        .allow_directory(&drive_path, true)
-                        .expect(&format!("Failed to allow access to drive {}", drive));
-                }
-            }
-        }
+    let fs_scope = app.fs_scope();
+    for root in &roots {
+        fs_scope
+            .allow_directory(root, true)
+            .map_err(|e| format!("Failed to allow access to {}: {}", root.display(), e))?;
     }
+    app.manage(PathGuard::new(roots));
 
     Ok(())
 }
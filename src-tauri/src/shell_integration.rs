@@ -0,0 +1,495 @@
+use portable_pty::CommandBuilder;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Longest OSC payload we are willing to buffer before giving up on a sequence
+const MAX_OSC_LEN: usize = 8192;
+// Upper bound on the output kept for a single command
+const MAX_CAPTURED_OUTPUT: usize = 1024 * 1024;
+
+const BASH_INTEGRATION: &str = r#"
+if [ -f "$HOME/.bashrc" ]; then . "$HOME/.bashrc"; fi
+
+__bitshift_osc() { printf '\033]%s\007' "$1"; }
+__bitshift_escape() {
+    local s="$1"
+    s="${s//\\/\\\\}"
+    s="${s//;/\\x3b}"
+    s="${s//$'\n'/\\x0a}"
+    printf '%s' "$s"
+}
+__bitshift_preexec() {
+    local cmd
+    cmd="$(HISTTIMEFORMAT= builtin history 1)"
+    [[ $cmd =~ ^\ *[0-9]+\*?\ +(.*)$ ]] && cmd="${BASH_REMATCH[1]}"
+    __bitshift_osc "133;E;$(__bitshift_escape "$cmd")"
+    __bitshift_osc "133;C"
+}
+__bitshift_precmd() {
+    local ec=$?
+    __bitshift_osc "133;D;$ec"
+    __bitshift_osc "7;file://${HOSTNAME}${PWD}"
+    __bitshift_osc "133;A"
+    return $ec
+}
+PROMPT_COMMAND="__bitshift_precmd${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+PS0='$(__bitshift_preexec)'"${PS0}"
+PS1="${PS1}\[\033]133;B\007\]"
+"#;
+
+const ZSH_ENV: &str = r#"
+if [ -f "${BITSHIFT_USER_ZDOTDIR:-$HOME}/.zshenv" ]; then . "${BITSHIFT_USER_ZDOTDIR:-$HOME}/.zshenv"; fi
+"#;
+
+const ZSH_INTEGRATION: &str = r#"
+ZDOTDIR="${BITSHIFT_USER_ZDOTDIR:-$HOME}"
+if [ -f "$ZDOTDIR/.zshrc" ]; then . "$ZDOTDIR/.zshrc"; fi
+
+__bitshift_osc() { printf '\033]%s\007' "$1" }
+__bitshift_escape() {
+    local s="$1"
+    s="${s//\\/\\\\}"
+    s="${s//;/\\x3b}"
+    s="${s//$'\n'/\\x0a}"
+    printf '%s' "$s"
+}
+__bitshift_preexec() {
+    __bitshift_osc "133;E;$(__bitshift_escape "$1")"
+    __bitshift_osc "133;C"
+}
+__bitshift_precmd() {
+    local ec=$?
+    __bitshift_osc "133;D;$ec"
+    __bitshift_osc "7;file://${HOST}${PWD}"
+    __bitshift_osc "133;A"
+}
+autoload -Uz add-zsh-hook
+precmd_functions=(__bitshift_precmd $precmd_functions)
+add-zsh-hook preexec __bitshift_preexec
+PS1="${PS1}%{$(printf '\033]133;B\007')%}"
+"#;
+
+const FISH_INTEGRATION: &str = r#"
+function __bitshift_osc
+    printf '\e]%s\a' $argv[1]
+end
+function __bitshift_escape
+    string replace -a '\\' '\\\\' -- $argv[1] | string replace -a ';' '\\x3b' | string join '\\x0a'
+end
+function __bitshift_preexec --on-event fish_preexec
+    __bitshift_osc "133;E;"(__bitshift_escape "$argv")
+    __bitshift_osc "133;C"
+end
+function __bitshift_postexec --on-event fish_postexec
+    __bitshift_osc "133;D;$status"
+end
+function __bitshift_prompt --on-event fish_prompt
+    __bitshift_osc "7;file://$hostname$PWD"
+    __bitshift_osc "133;A"
+end
+"#;
+
+/// A shell integration mark decoded from an OSC 133 or OSC 7 sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellMark {
+    PromptStart,
+    CommandStart,
+    CommandText(String),
+    CommandExecuted,
+    CommandFinished(Option<i32>),
+    Cwd(String),
+}
+
+/// A command observed through shell integration marks.
+#[derive(Debug, Serialize, Clone)]
+pub struct PtyCommand {
+    pub id: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub exit_code: Option<i32>,
+}
+
+/// Builds the command used to launch `shell` with our integration script
+/// sourced after the user's own rc files. Shells we don't know how to hook
+/// into are launched as-is.
+pub fn shell_command(shell: &str, integration_dir: &Path) -> Result<CommandBuilder, String> {
+    let shell_name = Path::new(shell)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let mut cmd = CommandBuilder::new(shell);
+    match shell_name.as_str() {
+        "bash" => {
+            let rc = write_script(integration_dir, "bash-integration.sh", BASH_INTEGRATION)?;
+            cmd.arg("--rcfile");
+            cmd.arg(rc);
+        }
+        "zsh" => {
+            let zdotdir = integration_dir.join("zsh");
+            write_script(&zdotdir, ".zshenv", ZSH_ENV)?;
+            write_script(&zdotdir, ".zshrc", ZSH_INTEGRATION)?;
+            let user_zdotdir = std::env::var("ZDOTDIR")
+                .ok()
+                .or_else(|| dirs::home_dir().map(|h| h.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            cmd.env("BITSHIFT_USER_ZDOTDIR", user_zdotdir);
+            cmd.env("ZDOTDIR", zdotdir);
+        }
+        "fish" => {
+            let script = write_script(integration_dir, "fish-integration.fish", FISH_INTEGRATION)?;
+            cmd.arg("--init-command");
+            cmd.arg(format!("source '{}'", script.display()));
+        }
+        _ => return Ok(cmd),
+    }
+    cmd.env("TERM_PROGRAM", "bitshift");
+    Ok(cmd)
+}
+
+fn write_script(dir: &Path, name: &str, contents: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create shell integration dir: {}", e))?;
+    let path = dir.join(name);
+    fs::write(&path, contents)
+        .map_err(|e| format!("Failed to write shell integration script: {}", e))?;
+    Ok(path)
+}
+
+enum ParseState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// A piece of PTY output: either plain terminal text (which may still contain
+/// non-OSC escape sequences) or a decoded shell integration mark.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedOutput {
+    Text(String),
+    Mark(ShellMark),
+}
+
+/// Incremental parser that picks shell integration sequences out of raw PTY
+/// output. Sequences may be split across reads, so partial payloads are kept
+/// between calls to `feed`.
+pub struct OscParser {
+    state: ParseState,
+    payload: String,
+}
+
+impl Default for OscParser {
+    fn default() -> Self {
+        Self {
+            state: ParseState::Ground,
+            payload: String::new(),
+        }
+    }
+}
+
+impl OscParser {
+    pub fn feed(&mut self, data: &str) -> Vec<ParsedOutput> {
+        let mut parsed = Vec::new();
+        let mut text = String::new();
+        for c in data.chars() {
+            self.state = match self.state {
+                ParseState::Ground if c == '\x1b' => ParseState::Escape,
+                ParseState::Ground => {
+                    text.push(c);
+                    ParseState::Ground
+                }
+                ParseState::Escape if c == ']' => {
+                    if !text.is_empty() {
+                        parsed.push(ParsedOutput::Text(std::mem::take(&mut text)));
+                    }
+                    self.payload.clear();
+                    ParseState::Osc
+                }
+                ParseState::Escape if c == '\x1b' => {
+                    text.push('\x1b');
+                    ParseState::Escape
+                }
+                ParseState::Escape => {
+                    text.push('\x1b');
+                    text.push(c);
+                    ParseState::Ground
+                }
+                ParseState::Osc | ParseState::OscEscape if c == '\x07' => {
+                    parsed.extend(parse_osc(&self.payload).map(ParsedOutput::Mark));
+                    ParseState::Ground
+                }
+                ParseState::OscEscape if c == '\\' => {
+                    parsed.extend(parse_osc(&self.payload).map(ParsedOutput::Mark));
+                    ParseState::Ground
+                }
+                ParseState::Osc | ParseState::OscEscape if c == '\x1b' => ParseState::OscEscape,
+                ParseState::OscEscape => ParseState::Ground,
+                ParseState::Osc if self.payload.len() >= MAX_OSC_LEN => ParseState::Ground,
+                ParseState::Osc => {
+                    self.payload.push(c);
+                    ParseState::Osc
+                }
+            };
+        }
+        if !text.is_empty() {
+            parsed.push(ParsedOutput::Text(text));
+        }
+        parsed
+    }
+}
+
+/// Removes ANSI escape sequences from terminal output and resolves carriage
+/// returns, leaving the text roughly as it appeared on screen.
+pub fn strip_ansi(data: &str) -> String {
+    let mut plain = String::with_capacity(data.len());
+    let mut chars = data.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS, APC, PM, SOS: terminated by BEL or ST
+                Some(']') | Some('P') | Some('_') | Some('^') | Some('X') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                // Character set designation takes one more byte
+                Some('(') | Some(')') | Some('*') | Some('+') => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' => {
+                // A bare carriage return rewinds to the start of the line
+                let line_start = plain.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                plain.truncate(line_start);
+            }
+            '\x08' => {
+                plain.pop();
+            }
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+fn parse_osc(payload: &str) -> Option<ShellMark> {
+    let (code, rest) = payload.split_once(';').unwrap_or((payload, ""));
+    match code {
+        "133" => {
+            let (kind, args) = rest.split_once(';').unwrap_or((rest, ""));
+            match kind {
+                "A" => Some(ShellMark::PromptStart),
+                "B" => Some(ShellMark::CommandStart),
+                "C" => Some(ShellMark::CommandExecuted),
+                "D" => Some(ShellMark::CommandFinished(
+                    args.split(';').next().and_then(|c| c.parse().ok()),
+                )),
+                "E" => Some(ShellMark::CommandText(unescape(args))),
+                _ => None,
+            }
+        }
+        "7" => {
+            // file://hostname/path -> /path
+            let path = rest.strip_prefix("file://").unwrap_or(rest);
+            let path = match path.find('/') {
+                Some(i) => &path[i..],
+                None => path,
+            };
+            Some(ShellMark::Cwd(path.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => result.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) => result.push(b as char),
+                    Err(_) => {
+                        result.push_str("\\x");
+                        result.push_str(&hex);
+                    }
+                }
+            }
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Turns a stream of marks into completed commands for one PTY session,
+/// capturing whatever the command printed between its start and end marks.
+#[derive(Default)]
+pub struct CommandTracker {
+    cwd: Option<String>,
+    text: Option<String>,
+    current: Option<PtyCommand>,
+    output: String,
+}
+
+pub enum CommandUpdate {
+    PromptReady,
+    Started(PtyCommand),
+    Finished(PtyCommand, String),
+}
+
+impl CommandTracker {
+    pub fn handle(&mut self, id: &str, parsed: ParsedOutput) -> Option<CommandUpdate> {
+        let mark = match parsed {
+            ParsedOutput::Text(text) => {
+                if self.current.is_some() && self.output.len() < MAX_CAPTURED_OUTPUT {
+                    self.output.push_str(&text);
+                }
+                return None;
+            }
+            ParsedOutput::Mark(mark) => mark,
+        };
+        match mark {
+            ShellMark::Cwd(cwd) => {
+                self.cwd = Some(cwd);
+                None
+            }
+            ShellMark::CommandText(text) => {
+                self.text = Some(text);
+                None
+            }
+            ShellMark::CommandExecuted => {
+                let command = PtyCommand {
+                    id: id.to_string(),
+                    command: self.text.take().unwrap_or_default(),
+                    cwd: self.cwd.clone(),
+                    started_at: chrono::Utc::now().timestamp_millis(),
+                    finished_at: None,
+                    exit_code: None,
+                };
+                self.current = Some(command.clone());
+                self.output.clear();
+                Some(CommandUpdate::Started(command))
+            }
+            ShellMark::CommandFinished(exit_code) => {
+                // The first prompt reports a status without any command having run
+                let mut command = self.current.take()?;
+                command.finished_at = Some(chrono::Utc::now().timestamp_millis());
+                command.exit_code = exit_code;
+                let output = strip_ansi(&std::mem::take(&mut self.output));
+                Some(CommandUpdate::Finished(command, output))
+            }
+            ShellMark::PromptStart => Some(CommandUpdate::PromptReady),
+            ShellMark::CommandStart => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(parsed: Vec<ParsedOutput>) -> Vec<ShellMark> {
+        parsed
+            .into_iter()
+            .filter_map(|p| match p {
+                ParsedOutput::Mark(mark) => Some(mark),
+                ParsedOutput::Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_marks_between_text() {
+        let mut parser = OscParser::default();
+        let parsed = parser.feed("before\x1b]133;A\x07$ \x1b]133;B\x07after");
+        assert_eq!(
+            parsed,
+            vec![
+                ParsedOutput::Text("before".into()),
+                ParsedOutput::Mark(ShellMark::PromptStart),
+                ParsedOutput::Text("$ ".into()),
+                ParsedOutput::Mark(ShellMark::CommandStart),
+                ParsedOutput::Text("after".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sequence_split_across_reads() {
+        let mut parser = OscParser::default();
+        assert!(marks(parser.feed("\x1b]133;D")).is_empty());
+        assert!(marks(parser.feed(";127")).is_empty());
+        assert_eq!(
+            marks(parser.feed("\x1b\\")),
+            vec![ShellMark::CommandFinished(Some(127))]
+        );
+    }
+
+    #[test]
+    fn decodes_command_text_and_cwd() {
+        let mut parser = OscParser::default();
+        let parsed = parser.feed("\x1b]133;E;echo a\\x3bb\\\\c\x07\x1b]7;file://host/home/me\x07");
+        assert_eq!(
+            marks(parsed),
+            vec![
+                ShellMark::CommandText("echo a;b\\c".into()),
+                ShellMark::Cwd("/home/me".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_other_escape_sequences_in_text() {
+        let mut parser = OscParser::default();
+        let parsed = parser.feed("\x1b[31mred\x1b[0m");
+        assert_eq!(
+            parsed,
+            vec![ParsedOutput::Text("\x1b[31mred\x1b[0m".into())]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_osc_codes() {
+        let mut parser = OscParser::default();
+        assert_eq!(parser.feed("\x1b]0;title\x07"), vec![]);
+    }
+
+    #[test]
+    fn strip_ansi_removes_escape_sequences() {
+        assert_eq!(strip_ansi("\x1b[1;32mok\x1b[0m\r\n"), "ok\n");
+        assert_eq!(strip_ansi("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_ansi("\x1b(Bplain"), "plain");
+    }
+
+    #[test]
+    fn strip_ansi_resolves_carriage_returns_and_backspaces() {
+        assert_eq!(strip_ansi("first\n10%\r50%\r100%"), "first\n100%");
+        assert_eq!(strip_ansi("abc\x08d"), "abd");
+    }
+}
@@ -1,10 +1,28 @@
-use portable_pty::{native_pty_system, Child, CommandBuilder, PtyPair, PtySize};
+use crate::asciicast::{self, CastEvent, Recorder};
+use crate::path_guard::authorize;
+use crate::shell_integration::{
+    shell_command, strip_ansi, CommandTracker, CommandUpdate, OscParser, PtyCommand,
+};
+use portable_pty::{native_pty_system, Child, PtyPair, PtySize};
+use regex::RegexBuilder;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::oneshot;
+
+// Number of finished commands remembered per terminal
+const MAX_COMMAND_HISTORY: usize = 200;
+// Raw output kept per terminal for search and export
+const MAX_SCROLLBACK_BYTES: usize = 2 * 1024 * 1024;
+// Longest pause reproduced when replaying a recording at normal speed
+const MAX_REPLAY_IDLE: f64 = 2.0;
+const DEFAULT_EXEC_TIMEOUT_MS: u64 = 60_000;
+// How long exec_in_pty waits for a freshly started shell to show its first prompt
+const INTEGRATION_WAIT: Duration = Duration::from_secs(5);
 
 #[derive(Serialize, Clone)]
 pub struct PtyOutput {
@@ -12,18 +30,163 @@ pub struct PtyOutput {
     pub id: String,
 }
 
+#[derive(Serialize, Debug)]
+pub struct ExecResult {
+    pub exit_code: Option<i32>,
+    pub output: String,
+    pub duration_ms: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub modified: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PtySearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Timestamped terminal output, trimmed from the front once it grows past
+/// `MAX_SCROLLBACK_BYTES`.
+pub struct Scrollback {
+    started: Instant,
+    started_at: i64,
+    cols: u16,
+    rows: u16,
+    events: VecDeque<CastEvent>,
+    bytes: usize,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: chrono::Utc::now().timestamp(),
+            cols: 80,
+            rows: 24,
+            events: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl Scrollback {
+    pub fn push_output(&mut self, data: &str) {
+        let event = CastEvent::output(self.started.elapsed().as_secs_f64(), data);
+        self.push_event(event);
+    }
+
+    pub fn push_resize(&mut self, cols: u16, rows: u16) {
+        self.cols = cols;
+        self.rows = rows;
+        let event = CastEvent::resize(self.started.elapsed().as_secs_f64(), cols, rows);
+        self.push_event(event);
+    }
+
+    // Every event counts towards the cap so the total stays in step with
+    // whatever is popped off the front.
+    fn push_event(&mut self, event: CastEvent) {
+        self.bytes += event.data.len();
+        self.events.push_back(event);
+        while self.bytes > MAX_SCROLLBACK_BYTES {
+            match self.events.pop_front() {
+                Some(event) => self.bytes -= event.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    pub fn raw_text(&self) -> String {
+        self.events
+            .iter()
+            .filter(|event| event.kind == "o")
+            .map(|event| event.data.as_str())
+            .collect()
+    }
+
+    pub fn to_asciicast(&self) -> String {
+        let mut cast = asciicast::header(self.cols, self.rows, self.started_at);
+        for event in &self.events {
+            cast.push('\n');
+            cast.push_str(&event.to_line());
+        }
+        cast.push('\n');
+        cast
+    }
+}
+
+/// Turns PTY reads into text. A multi-byte character split across two reads
+/// is held back until the rest of it arrives instead of becoming U+FFFD.
+#[derive(Default)]
+struct Utf8Decoder {
+    incomplete: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    fn decode(&mut self, bytes: &[u8]) -> String {
+        self.incomplete.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut start = 0;
+        while start < self.incomplete.len() {
+            match std::str::from_utf8(&self.incomplete[start..]) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    start = self.incomplete.len();
+                }
+                Err(e) => {
+                    let valid_end = start + e.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.incomplete[start..valid_end]));
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            start = valid_end + len;
+                        }
+                        // The read ended inside a character; keep its start
+                        None => {
+                            start = valid_end;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.incomplete.drain(..start);
+        text
+    }
+}
+
+/// Session data shared between the reader thread and the commands that
+/// inspect or drive the terminal.
+#[derive(Default)]
+pub struct SessionShared {
+    pub commands: Vec<PtyCommand>,
+    pub scrollback: Scrollback,
+    pub recorder: Option<Recorder>,
+    pub integration_active: bool,
+    // exec_in_pty callers waiting for the next command to start
+    pending_exec: VecDeque<oneshot::Sender<ExecResult>>,
+    // exec_in_pty caller waiting for the running command to finish
+    active_exec: Option<oneshot::Sender<ExecResult>>,
+}
+
+pub struct PtySession {
+    pub pair: PtyPair,
+    // Held so the shell and its output pipe live as long as the session
+    _child: Box<dyn Child + Send + Sync + 'static>,
+    _reader: Box<dyn std::io::Read + Send + 'static>,
+    pub writer: Box<dyn std::io::Write + Send + 'static>,
+    pub shared: Arc<Mutex<SessionShared>>,
+}
+
 pub struct PtyState {
-    pub ptys: Mutex<
-        HashMap<
-            String,
-            (
-                PtyPair,
-                Box<dyn Child + Send + Sync + 'static>,
-                Box<dyn std::io::Read + Send + 'static>,
-                Box<dyn std::io::Write + Send + 'static>,
-            ),
-        >,
-    >,
+    pub ptys: Mutex<HashMap<String, PtySession>>,
 }
 
 impl Default for PtyState {
@@ -34,7 +197,12 @@ impl Default for PtyState {
     }
 }
 #[tauri::command]
-pub fn start_pty(id: String, app_handle: AppHandle) -> Result<(), String> {
+pub fn start_pty(
+    id: String,
+    shell: Option<String>,
+    record: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
     println!("[start_pty] Starting PTY setup for ID: {}", id);
 
     // Check if PTY already exists and clean it up if it does
@@ -60,15 +228,22 @@ pub fn start_pty(id: String, app_handle: AppHandle) -> Result<(), String> {
             format!("Failed to open PTY: {}", e)
         })?;
 
-    let shell = if cfg!(target_os = "windows") {
-        "cmd.exe"
-    } else {
-        "bash"
-    };
+    let shell = shell.unwrap_or_else(|| {
+        if cfg!(target_os = "windows") {
+            "cmd.exe".to_string()
+        } else {
+            "bash".to_string()
+        }
+    });
 
     println!("[start_pty] Launching shell {} for terminal {}", shell, id);
 
-    let mut cmd = CommandBuilder::new(shell);
+    let integration_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?
+        .join("shell-integration");
+    let mut cmd = shell_command(&shell, &integration_dir)?;
     cmd.cwd(std::env::current_dir().map_err(|e| {
         println!("[start_pty] Failed to get current dir: {}", e);
         format!("Failed to get current dir: {}", e)
@@ -108,12 +283,32 @@ pub fn start_pty(id: String, app_handle: AppHandle) -> Result<(), String> {
             format!("Failed to take writer: {}", e)
         })?;
 
-    let master = pty_pair;
+    let mut shared = SessionShared::default();
+    if record.unwrap_or(false) {
+        let path = recordings_dir(&app_handle)?.join(format!(
+            "{}-{}.cast",
+            recording_stem(&id),
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        println!(
+            "[start_pty] Recording terminal {} to {}",
+            id,
+            path.display()
+        );
+        shared.recorder = Some(Recorder::create(path, 80, 24)?);
+    }
+    let shared = Arc::new(Mutex::new(shared));
 
     // Insert into state
     state.ptys.lock().unwrap().insert(
         id.clone(),
-        (master, child, Box::new(reader), Box::new(writer)),
+        PtySession {
+            pair: pty_pair,
+            _child: child,
+            _reader: Box::new(reader),
+            writer: Box::new(writer),
+            shared: shared.clone(),
+        },
     );
     println!("[start_pty] PTY inserted into state for terminal {}", id);
 
@@ -129,13 +324,72 @@ pub fn start_pty(id: String, app_handle: AppHandle) -> Result<(), String> {
     let id_clone = id.clone();
     thread::spawn(move || {
         let mut buffer = [0; 1024];
+        let mut decoder = Utf8Decoder::default();
         let mut previous_output = String::new();
+        let mut parser = OscParser::default();
+        let mut tracker = CommandTracker::default();
 
         println!("[reader_thread] Starting PTY reader loop for terminal {}", id_clone);
         loop {
             match reader_for_thread.read(&mut buffer) {
                 Ok(n) if n > 0 => {
-                    let output = String::from_utf8_lossy(&buffer[..n]).to_string();
+                    let output = decoder.decode(&buffer[..n]);
+                    if output.is_empty() {
+                        continue;
+                    }
+                    {
+                        let mut shared = shared.lock().unwrap();
+                        shared.scrollback.push_output(&output);
+                        if let Some(Err(e)) = shared.recorder.as_mut().map(|r| r.output(&output)) {
+                            println!(
+                                "[reader_thread] Stopping recording for terminal {}: {}",
+                                id_clone, e
+                            );
+                            shared.recorder = None;
+                        }
+                    }
+
+                    for parsed in parser.feed(&output) {
+                        let command = match tracker.handle(&id_clone, parsed) {
+                            Some(CommandUpdate::PromptReady) => {
+                                shared.lock().unwrap().integration_active = true;
+                                continue;
+                            }
+                            Some(CommandUpdate::Started(command)) => {
+                                let mut shared = shared.lock().unwrap();
+                                if shared.active_exec.is_none() {
+                                    // Skip callers that already gave up waiting
+                                    while let Some(sender) = shared.pending_exec.pop_front() {
+                                        if !sender.is_closed() {
+                                            shared.active_exec = Some(sender);
+                                            break;
+                                        }
+                                    }
+                                }
+                                command
+                            }
+                            Some(CommandUpdate::Finished(command, captured)) => {
+                                let mut shared = shared.lock().unwrap();
+                                if let Some(sender) = shared.active_exec.take() {
+                                    let _ = sender.send(ExecResult {
+                                        exit_code: command.exit_code,
+                                        output: captured,
+                                        duration_ms: command.finished_at.unwrap_or_default()
+                                            - command.started_at,
+                                    });
+                                }
+                                if shared.commands.len() >= MAX_COMMAND_HISTORY {
+                                    shared.commands.remove(0);
+                                }
+                                shared.commands.push(command.clone());
+                                command
+                            }
+                            None => continue,
+                        };
+                        if let Err(e) = app_handle_clone.emit("pty_command", &command) {
+                            println!("[reader_thread] Failed to emit pty_command event: {}", e);
+                        }
+                    }
 
                     if output != previous_output {
                         println!("[PTY Output] Terminal {}: {:?}", id_clone, output);
@@ -180,11 +434,13 @@ pub fn write_to_pty(id: String, data: String, app_handle: AppHandle) -> Result<(
     let state = app_handle.state::<PtyState>();
     let mut ptys = state.ptys.lock().unwrap();
 
-    if let Some((_, _, _, ref mut writer)) = ptys.get_mut(&id) {
-        writer
+    if let Some(session) = ptys.get_mut(&id) {
+        session
+            .writer
             .write_all(data.as_bytes())
             .map_err(|e| format!("Failed to write to PTY: {}", e))?;
-        writer
+        session
+            .writer
             .flush()
             .map_err(|e| format!("Failed to flush PTY: {}", e))?;
         Ok(())
@@ -216,8 +472,9 @@ pub fn resize_pty(id: String, rows: u16, cols: u16, app_handle: AppHandle) -> Re
     let state = app_handle.state::<PtyState>();
     let ptys = state.ptys.lock().unwrap();
 
-    if let Some((master, _, _, _)) = ptys.get(&id) {
-        master
+    if let Some(session) = ptys.get(&id) {
+        session
+            .pair
             .master
             .resize(PtySize {
                 rows,
@@ -226,8 +483,344 @@ pub fn resize_pty(id: String, rows: u16, cols: u16, app_handle: AppHandle) -> Re
                 pixel_height: 0,
             })
             .map_err(|e| format!("Failed to resize PTY: {}", e))?;
+        let mut shared = session.shared.lock().unwrap();
+        shared.scrollback.push_resize(cols, rows);
+        if let Some(Err(e)) = shared.recorder.as_mut().map(|r| r.resize(cols, rows)) {
+            println!("[resize_pty] Stopping recording for terminal {}: {}", id, e);
+            shared.recorder = None;
+        }
         Ok(())
     } else {
         Err(format!("No PTY session found for terminal {}", id))
     }
 }
+
+#[tauri::command]
+pub fn get_pty_commands(id: String, app_handle: AppHandle) -> Result<Vec<PtyCommand>, String> {
+    let state = app_handle.state::<PtyState>();
+    let ptys = state.ptys.lock().unwrap();
+
+    if let Some(session) = ptys.get(&id) {
+        Ok(session.shared.lock().unwrap().commands.clone())
+    } else {
+        Err(format!("No PTY session found for terminal {}", id))
+    }
+}
+
+/// Runs `command` in an existing terminal so the user can watch it, and waits
+/// for the shell integration marks to report its completion. `timeout` is in
+/// milliseconds.
+#[tauri::command]
+pub async fn exec_in_pty(
+    id: String,
+    command: String,
+    timeout: Option<u64>,
+    app_handle: AppHandle,
+) -> Result<ExecResult, String> {
+    let timeout = Duration::from_millis(timeout.unwrap_or(DEFAULT_EXEC_TIMEOUT_MS));
+    let shared = {
+        let state = app_handle.state::<PtyState>();
+        let ptys = state.ptys.lock().unwrap();
+        ptys.get(&id)
+            .map(|session| session.shared.clone())
+            .ok_or_else(|| format!("No PTY session found for terminal {}", id))?
+    };
+
+    let waited = std::time::Instant::now();
+    while !shared.lock().unwrap().integration_active {
+        if waited.elapsed() >= INTEGRATION_WAIT {
+            return Err(format!(
+                "Shell integration is not active for terminal {}",
+                id
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let (sender, receiver) = oneshot::channel();
+    shared.lock().unwrap().pending_exec.push_back(sender);
+
+    println!("[exec_in_pty] Running {:?} in terminal {}", command, id);
+    write_to_pty(
+        id.clone(),
+        format!("{}\r", command.trim_end()),
+        app_handle.clone(),
+    )?;
+
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(_)) => Err(format!(
+            "Terminal {} closed before the command finished",
+            id
+        )),
+        Err(_) => Err(format!(
+            "Command timed out after {} ms in terminal {}",
+            timeout.as_millis(),
+            id
+        )),
+    }
+}
+
+/// Searches the terminal's scrollback with ANSI sequences removed. Matches are
+/// reported per line with character offsets into that line.
+#[tauri::command]
+pub fn search_pty_output(
+    id: String,
+    pattern: String,
+    regex: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<Vec<PtySearchMatch>, String> {
+    let text = {
+        let state = app_handle.state::<PtyState>();
+        let ptys = state.ptys.lock().unwrap();
+        let session = ptys
+            .get(&id)
+            .ok_or_else(|| format!("No PTY session found for terminal {}", id))?;
+        let raw = session.shared.lock().unwrap().scrollback.raw_text();
+        strip_ansi(&raw)
+    };
+
+    let pattern = if regex.unwrap_or(false) {
+        pattern
+    } else {
+        regex::escape(&pattern)
+    };
+    let re = RegexBuilder::new(&pattern)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))?;
+
+    let mut matches = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        for m in re.find_iter(line) {
+            if m.start() == m.end() {
+                continue;
+            }
+            matches.push(PtySearchMatch {
+                line: line_number,
+                start: line[..m.start()].chars().count(),
+                end: line[..m.end()].chars().count(),
+                text: line.to_string(),
+            });
+        }
+    }
+    Ok(matches)
+}
+
+/// Writes the terminal's scrollback to `path`. Paths ending in `.cast` are
+/// written as asciicast v2 with the original timing; anything else is written
+/// as a plain transcript, optionally with ANSI sequences removed.
+#[tauri::command]
+pub async fn export_pty_log(
+    id: String,
+    path: String,
+    strip_ansi: Option<bool>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let target = authorize(&app_handle, &path, "write").await?;
+    let state = app_handle.state::<PtyState>();
+    let ptys = state.ptys.lock().unwrap();
+    let session = ptys
+        .get(&id)
+        .ok_or_else(|| format!("No PTY session found for terminal {}", id))?;
+    let shared = session.shared.lock().unwrap();
+
+    let is_cast = std::path::Path::new(&path)
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("cast"))
+        .unwrap_or(false);
+    let contents = if is_cast {
+        shared.scrollback.to_asciicast()
+    } else if strip_ansi.unwrap_or(true) {
+        crate::shell_integration::strip_ansi(&shared.scrollback.raw_text())
+    } else {
+        shared.scrollback.raw_text()
+    };
+
+    std::fs::write(&target, contents)
+        .map_err(|e| format!("Failed to write terminal log {}: {}", path, e))?;
+    println!("[export_pty_log] Exported terminal {} to {}", id, path);
+    Ok(())
+}
+
+pub(crate) fn recordings_dir(app_handle: &AppHandle) -> Result<std::path::PathBuf, String> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .map(|dir| dir.join("recordings"))
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+// Terminal ids come from the frontend, so anything that could step outside
+// the recordings folder is replaced before the id becomes part of a file name.
+fn recording_stem(id: &str) -> String {
+    let stem: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "terminal".to_string()
+    } else {
+        stem
+    }
+}
+
+#[tauri::command]
+pub fn list_recordings(app_handle: AppHandle) -> Result<Vec<RecordingInfo>, String> {
+    let dir = recordings_dir(&app_handle)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut recordings = Vec::new();
+    for entry in std::fs::read_dir(&dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().map(|ext| ext != "cast").unwrap_or(true) {
+            continue;
+        }
+        let metadata = std::fs::metadata(&path).map_err(|e| e.to_string())?;
+        recordings.push(RecordingInfo {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            path: path.to_string_lossy().into(),
+            size: metadata.len(),
+            modified: metadata
+                .modified()
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        });
+    }
+    recordings.sort_by_key(|recording| std::cmp::Reverse(recording.modified));
+    Ok(recordings)
+}
+
+/// Plays an asciicast recording back into terminal `id` as `pty_output`
+/// events. `speed` scales the original timing (2.0 plays twice as fast), and
+/// long idle gaps are shortened so replays don't stall.
+#[tauri::command]
+pub async fn replay_recording(
+    path: String,
+    id: String,
+    speed: Option<f64>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let speed = speed.unwrap_or(1.0);
+    if speed <= 0.0 {
+        return Err("Replay speed must be greater than zero".to_string());
+    }
+
+    let source = authorize(&app_handle, &path, "read").await?;
+    let events = asciicast::read_events(&source)?;
+    println!(
+        "[replay_recording] Replaying {} events from {} into terminal {}",
+        events.len(),
+        path,
+        id
+    );
+
+    let mut previous = 0.0;
+    for event in events {
+        let idle = (event.time - previous).clamp(0.0, MAX_REPLAY_IDLE);
+        previous = event.time;
+        tokio::time::sleep(Duration::from_secs_f64(idle / speed)).await;
+
+        if event.kind != "o" {
+            continue;
+        }
+        app_handle
+            .emit(
+                "pty_output",
+                PtyOutput {
+                    data: event.data,
+                    id: id.clone(),
+                },
+            )
+            .map_err(|e| format!("Failed to emit event: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_decoder_joins_characters_split_across_reads() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "é€😀".as_bytes();
+        assert_eq!(decoder.decode(&bytes[..1]), "");
+        assert_eq!(decoder.decode(&bytes[1..4]), "é");
+        assert_eq!(decoder.decode(&bytes[4..7]), "€");
+        assert_eq!(decoder.decode(&bytes[7..]), "😀");
+        assert!(decoder.incomplete.is_empty());
+    }
+
+    #[test]
+    fn utf8_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"a\xFFb\xE2\x82"), "a\u{FFFD}b");
+        assert_eq!(decoder.decode(b"\xACc"), "€c");
+        assert_eq!(decoder.decode(b"\xE2\x82d"), "\u{FFFD}d");
+    }
+
+    #[test]
+    fn scrollback_trims_oldest_output_past_the_cap() {
+        let mut scrollback = Scrollback::default();
+        let chunk = "a".repeat(MAX_SCROLLBACK_BYTES / 2);
+        scrollback.push_output("first");
+        scrollback.push_output(&chunk);
+        scrollback.push_output(&chunk);
+        assert_eq!(scrollback.bytes, MAX_SCROLLBACK_BYTES);
+        assert_eq!(scrollback.raw_text(), chunk.repeat(2));
+
+        scrollback.push_output("b");
+        assert_eq!(scrollback.events.len(), 2);
+        assert_eq!(scrollback.bytes, chunk.len() + 1);
+    }
+
+    #[test]
+    fn scrollback_counts_resize_events() {
+        let mut scrollback = Scrollback::default();
+        let chunk = "a".repeat(MAX_SCROLLBACK_BYTES - 10);
+        scrollback.push_resize(120, 40);
+        scrollback.push_output(&chunk);
+        assert_eq!(scrollback.bytes, chunk.len() + "120x40".len());
+
+        // Popping the resize event must not leave the byte count behind
+        scrollback.push_output("0123456789");
+        let expected: usize = scrollback.events.iter().map(|e| e.data.len()).sum();
+        assert_eq!(scrollback.bytes, expected);
+        assert!(scrollback.bytes <= MAX_SCROLLBACK_BYTES);
+        assert_eq!((scrollback.cols, scrollback.rows), (120, 40));
+    }
+
+    #[test]
+    fn recording_stem_keeps_ids_inside_the_recordings_dir() {
+        assert_eq!(
+            recording_stem("0b7c1a2e-5f1d-4c1e-9a51-2f1e0c3d4b5a"),
+            "0b7c1a2e-5f1d-4c1e-9a51-2f1e0c3d4b5a"
+        );
+        assert_eq!(recording_stem("../../etc/passwd"), "______etc_passwd");
+        assert_eq!(recording_stem("a\\b"), "a_b");
+        assert_eq!(recording_stem(""), "terminal");
+    }
+
+    #[test]
+    fn scrollback_raw_text_skips_resize_events() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_output("one ");
+        scrollback.push_resize(100, 30);
+        scrollback.push_output("two");
+        assert_eq!(scrollback.raw_text(), "one two");
+    }
+}
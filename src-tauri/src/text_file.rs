@@ -0,0 +1,367 @@
+use crate::path_guard::authorize;
+use crate::search::BINARY_SNIFF_LEN;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, Metadata};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "latin-1")]
+    Latin1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextFile {
+    pub path: String,
+    /// The text with line endings normalized to `\n`; empty for binary files
+    pub content: String,
+    /// None for binary files
+    pub encoding: Option<TextEncoding>,
+    /// The most common line ending, used again when saving
+    pub line_ending: LineEnding,
+    pub mixed_line_endings: bool,
+    pub binary: bool,
+    pub size: u64,
+    /// Pass back to `write_text_file` to detect changes made elsewhere
+    pub modified_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SavedTextFile {
+    pub path: String,
+    pub size: u64,
+    pub modified_ms: u64,
+}
+
+fn modified_ms(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Decodes a file by its byte order mark, then as UTF-8, then as Latin-1.
+/// Returns None for binary content.
+fn decode(bytes: &[u8]) -> Option<(String, TextEncoding)> {
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        return Some((
+            String::from_utf8_lossy(rest).into_owned(),
+            TextEncoding::Utf8Bom,
+        ));
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16LE_BOM) {
+        return Some((
+            decode_utf16(rest, u16::from_le_bytes),
+            TextEncoding::Utf16Le,
+        ));
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16BE_BOM) {
+        return Some((
+            decode_utf16(rest, u16::from_be_bytes),
+            TextEncoding::Utf16Be,
+        ));
+    }
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some((text.to_string(), TextEncoding::Utf8)),
+        Err(_) => Some((
+            bytes.iter().map(|&b| b as char).collect(),
+            TextEncoding::Latin1,
+        )),
+    }
+}
+
+fn encode(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, String> {
+    let bytes = match encoding {
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+        TextEncoding::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+        TextEncoding::Utf16Le => UTF16LE_BOM
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+        TextEncoding::Utf16Be => UTF16BE_BOM
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+        TextEncoding::Latin1 => text
+            .chars()
+            .map(|c| {
+                u8::try_from(c)
+                    .map_err(|_| format!("'{}' can't be saved in Latin-1, use UTF-8 instead", c))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(bytes)
+}
+
+/// The most common line ending in `text`, and whether others appear too.
+fn detect_line_ending(text: &str) -> (LineEnding, bool) {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+
+    let kinds = [
+        (LineEnding::Lf, lf),
+        (LineEnding::Crlf, crlf),
+        (LineEnding::Cr, cr),
+    ];
+    let used = kinds.iter().filter(|(_, count)| *count > 0).count();
+    let dominant = kinds
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map_or(LineEnding::Lf, |(ending, _)| *ending);
+    (dominant, used > 1)
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+fn apply_line_ending(text: &str, ending: LineEnding) -> String {
+    let text = normalize_line_endings(text);
+    match ending {
+        LineEnding::Lf => text,
+        LineEnding::Crlf => text.replace('\n', "\r\n"),
+        LineEnding::Cr => text.replace('\n', "\r"),
+    }
+}
+
+fn read_text(path: &Path) -> Result<TextFile, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let mut file = TextFile {
+        path: path.to_string_lossy().to_string(),
+        content: String::new(),
+        encoding: None,
+        line_ending: LineEnding::Lf,
+        mixed_line_endings: false,
+        binary: true,
+        size: metadata.len(),
+        modified_ms: modified_ms(&metadata),
+    };
+    if let Some((text, encoding)) = decode(&bytes) {
+        let (line_ending, mixed) = detect_line_ending(&text);
+        file.content = normalize_line_endings(&text);
+        file.encoding = Some(encoding);
+        file.line_ending = line_ending;
+        file.mixed_line_endings = mixed;
+        file.binary = false;
+    }
+    Ok(file)
+}
+
+/// Reads a file for editing, detecting its encoding, line endings and
+/// whether it's binary.
+#[tauri::command]
+pub async fn read_text_file(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<TextFile, String> {
+    read_text(&authorize(&app_handle, &path, "read").await?)
+}
+
+/// The file a write to `path` should replace: the target of a symlink, so
+/// the link itself survives the atomic rename.
+pub(crate) fn write_target(path: &Path) -> Result<PathBuf, String> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => path
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve symlink {}: {}", path.display(), e)),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// Saves editor text. The encoding and line ending default to the ones the
+/// file has on disk, or UTF-8 and `\n` for new files. With
+/// `expected_modified_ms` from `read_text_file`, the write is refused if the
+/// file was changed since. A binary file is only replaced when
+/// `overwrite_binary` is set. The file is replaced atomically, and a symlink
+/// is saved through to its target.
+#[tauri::command]
+pub async fn write_text_file(
+    app_handle: tauri::AppHandle,
+    path: String,
+    content: String,
+    encoding: Option<TextEncoding>,
+    line_ending: Option<LineEnding>,
+    expected_modified_ms: Option<u64>,
+    overwrite_binary: Option<bool>,
+) -> Result<SavedTextFile, String> {
+    let requested = authorize(&app_handle, &path, "write").await?;
+    let target = write_target(&requested)?;
+    let path = target.as_path();
+    let existing = path.exists().then(|| read_text(path)).transpose()?;
+
+    if existing.as_ref().is_some_and(|file| file.binary) && !overwrite_binary.unwrap_or(false) {
+        return Err(format!(
+            "{} is a binary file and would be overwritten with text",
+            path.display()
+        ));
+    }
+
+    if let (Some(existing), Some(expected)) = (&existing, expected_modified_ms) {
+        if existing.modified_ms != expected {
+            return Err(format!(
+                "{} was changed on disk since it was opened",
+                path.display()
+            ));
+        }
+    }
+
+    let encoding = encoding
+        .or_else(|| existing.as_ref().and_then(|file| file.encoding))
+        .unwrap_or(TextEncoding::Utf8);
+    let line_ending = line_ending
+        .or_else(|| existing.as_ref().map(|file| file.line_ending))
+        .unwrap_or(LineEnding::Lf);
+    let bytes = encode(&apply_line_ending(&content, line_ending), encoding)?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)
+        .map_err(|e| format!("Failed to create temp file for {}: {}", path.display(), e))?;
+    temp.write_all(&bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::set_permissions(temp.path(), metadata.permissions());
+    }
+    temp.persist(path)
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(SavedTextFile {
+        path: requested.to_string_lossy().to_string(),
+        size: metadata.len(),
+        modified_ms: modified_ms(&metadata),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_detects_byte_order_marks() {
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFhi"),
+            Some(("hi".to_string(), TextEncoding::Utf8Bom))
+        );
+        assert_eq!(
+            decode(b"\xFF\xFEh\0i\0"),
+            Some(("hi".to_string(), TextEncoding::Utf16Le))
+        );
+        assert_eq!(
+            decode(b"\xFE\xFF\0h\0i"),
+            Some(("hi".to_string(), TextEncoding::Utf16Be))
+        );
+    }
+
+    #[test]
+    fn decode_falls_back_to_latin1() {
+        assert_eq!(
+            decode("héllo".as_bytes()),
+            Some(("héllo".to_string(), TextEncoding::Utf8))
+        );
+        assert_eq!(
+            decode(b"caf\xE9"),
+            Some(("café".to_string(), TextEncoding::Latin1))
+        );
+    }
+
+    #[test]
+    fn decode_rejects_binary() {
+        assert_eq!(decode(b"\x7FELF\0\x01\x02"), None);
+    }
+
+    #[test]
+    fn encode_round_trips_through_decode() {
+        for encoding in [
+            TextEncoding::Utf8,
+            TextEncoding::Utf8Bom,
+            TextEncoding::Utf16Le,
+            TextEncoding::Utf16Be,
+            TextEncoding::Latin1,
+        ] {
+            let bytes = encode("café", encoding).unwrap();
+            assert_eq!(decode(&bytes), Some(("café".to_string(), encoding)));
+        }
+        assert!(encode("€", TextEncoding::Latin1).is_err());
+    }
+
+    #[test]
+    fn detect_line_ending_picks_the_most_common() {
+        assert_eq!(detect_line_ending("a\nb\n"), (LineEnding::Lf, false));
+        assert_eq!(detect_line_ending("a\r\nb\r\n"), (LineEnding::Crlf, false));
+        assert_eq!(detect_line_ending("a\rb\r"), (LineEnding::Cr, false));
+        assert_eq!(
+            detect_line_ending("a\r\nb\r\nc\n"),
+            (LineEnding::Crlf, true)
+        );
+        assert_eq!(detect_line_ending("no newline"), (LineEnding::Lf, false));
+    }
+
+    #[test]
+    fn apply_line_ending_normalizes_first() {
+        assert_eq!(
+            apply_line_ending("a\r\nb\rc\n", LineEnding::Crlf),
+            "a\r\nb\r\nc\r\n"
+        );
+        assert_eq!(apply_line_ending("a\r\nb\n", LineEnding::Lf), "a\nb\n");
+    }
+
+    #[test]
+    fn write_target_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("real.txt");
+        fs::write(&file, "text").unwrap();
+        assert_eq!(write_target(&file).unwrap(), file);
+
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.txt");
+            std::os::unix::fs::symlink(&file, &link).unwrap();
+            assert_eq!(write_target(&link).unwrap(), file.canonicalize().unwrap());
+        }
+    }
+}
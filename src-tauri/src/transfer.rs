@@ -0,0 +1,703 @@
+use crate::file_history::{FileHistory, FileOp};
+use crate::file_jobs::{JobProgress, Progress};
+use crate::file_ops::path_exists;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, FileTimes, Metadata};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Files are copied in chunks of this size so progress and cancellation are
+// noticed within large files
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// What to do when a copy or move would replace an existing item.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictMode {
+    Skip,
+    /// Replace files, merging the contents of folders
+    Overwrite,
+    /// Give the new item a free name like `name (1).ext`
+    KeepBoth,
+    /// Leave the item alone and report the conflict so the user can choose
+    #[default]
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Copied,
+    Moved,
+    Overwritten,
+    Renamed,
+    Skipped,
+    Conflict,
+    Failed,
+    /// Stopped partway by `cancel_file_op`
+    Cancelled,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferItem {
+    pub source: String,
+    pub destination: String,
+    pub status: ItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferResult {
+    /// False if any item failed or needs a conflict decision
+    pub success: bool,
+    pub message: String,
+    pub items: Vec<TransferItem>,
+    pub cancelled: bool,
+    /// How much of the work was done, also when cancelled
+    pub progress: JobProgress,
+}
+
+fn preserve_times(path: &Path, metadata: &Metadata) {
+    let mut times = FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    // Read-only copies can't be opened for writing, but a read handle is
+    // enough to set times on most platforms
+    if let Ok(file) = fs::File::options()
+        .write(true)
+        .open(path)
+        .or_else(|_| fs::File::open(path))
+    {
+        let _ = file.set_times(times);
+    }
+}
+
+fn copy_chunks(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    progress: &Progress,
+) -> io::Result<()> {
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+    loop {
+        progress.check()?;
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read])?;
+        progress.add_bytes(read as u64);
+    }
+}
+
+/// Copies a file's contents and permissions, removing the partial copy if
+/// the job is cancelled.
+fn copy_file(
+    source: &Path,
+    destination: &Path,
+    metadata: &Metadata,
+    progress: &Progress,
+) -> io::Result<()> {
+    let mut reader = fs::File::open(source)?;
+    let mut writer = fs::File::create_new(destination)?;
+    if let Err(e) = copy_chunks(&mut reader, &mut writer, progress) {
+        drop(writer);
+        let _ = fs::remove_file(destination);
+        return Err(e);
+    }
+    fs::set_permissions(destination, metadata.permissions())
+}
+
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    let target = fs::read_link(source)?;
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&target, destination)
+    }
+    #[cfg(windows)]
+    {
+        if source.is_dir() {
+            std::os::windows::fs::symlink_dir(&target, destination)
+        } else {
+            std::os::windows::fs::symlink_file(&target, destination)
+        }
+    }
+}
+
+/// Copies a file, symlink or folder to a `destination` that doesn't exist
+/// yet. Symlinks are recreated rather than followed, and permissions and
+/// modification times are kept. Items inside a folder that can't be copied
+/// are collected in `failures` without stopping the rest, unless the job
+/// is cancelled.
+fn copy_tree(
+    source: &Path,
+    destination: &Path,
+    failures: &mut Vec<(PathBuf, PathBuf, io::Error)>,
+    progress: &Progress,
+) -> io::Result<()> {
+    progress.check()?;
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        copy_symlink(source, destination)?;
+        progress.item_done(source);
+        return Ok(());
+    }
+
+    if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    failures.push((source.to_path_buf(), destination.to_path_buf(), e));
+                    continue;
+                }
+            };
+            let (from, to) = (entry.path(), destination.join(entry.file_name()));
+            match copy_tree(&from, &to, failures, progress) {
+                Ok(_) => {}
+                Err(e) if progress.is_cancelled() => return Err(e),
+                Err(e) => failures.push((from, to, e)),
+            }
+        }
+        // Applied after the contents so read-only folders can be filled
+        fs::set_permissions(destination, metadata.permissions())?;
+    } else {
+        copy_file(source, destination, &metadata, progress)?;
+    }
+    preserve_times(destination, &metadata);
+    progress.item_done(source);
+    Ok(())
+}
+
+/// Renames `from` to `to`, falling back to copy and delete when they're on
+/// different devices. The source is only deleted once everything copied.
+pub(crate) fn move_path(from: &Path, to: &Path, progress: &Progress) -> io::Result<()> {
+    progress.check()?;
+    match fs::rename(from, to) {
+        Ok(()) => {
+            progress.item_done(from);
+            return Ok(());
+        }
+        // Only a move across devices needs the copy; anything else is a
+        // real failure
+        Err(e) if e.kind() != io::ErrorKind::CrossesDevices => return Err(e),
+        Err(_) => {}
+    }
+
+    let mut failures = Vec::new();
+    let copied = copy_tree(from, to, &mut failures, progress).and_then(|_| match failures.pop() {
+        Some((path, _, e)) => Err(io::Error::new(
+            e.kind(),
+            format!("{}: {}", path.display(), e),
+        )),
+        None => Ok(()),
+    });
+    if let Err(e) = copied {
+        if path_exists(to) {
+            let _ = remove_path(to);
+        }
+        return Err(e);
+    }
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// The first of `name (1).ext`, `name (2).ext`, ... that doesn't exist yet.
+fn free_name(path: &Path, is_dir: bool) -> PathBuf {
+    let (stem, extension) = if is_dir {
+        (path.file_name(), None)
+    } else {
+        (path.file_stem(), path.extension())
+    };
+    let stem = stem.unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| {
+            let name = match extension {
+                Some(extension) => format!("{} ({}).{}", stem, n, extension.to_string_lossy()),
+                None => format!("{} ({})", stem, n),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !path_exists(candidate))
+        .expect("some numbered name is free")
+}
+
+/// Where `destination` will be once it exists, for comparing with the
+/// source. Only its parent has to exist.
+fn resolve(destination: &Path) -> Option<PathBuf> {
+    let parent = destination.parent()?.canonicalize().ok()?;
+    Some(parent.join(destination.file_name()?))
+}
+
+/// A copy or move of one item, collecting a result per item it touched and
+/// the journal entries needed to undo it.
+pub struct Transfer<'a> {
+    history: &'a FileHistory,
+    progress: &'a Progress,
+    mode: ConflictMode,
+    moving: bool,
+    items: Vec<TransferItem>,
+    ops: Vec<FileOp>,
+}
+
+impl<'a> Transfer<'a> {
+    pub fn new(
+        history: &'a FileHistory,
+        progress: &'a Progress,
+        mode: ConflictMode,
+        moving: bool,
+    ) -> Self {
+        Transfer {
+            history,
+            progress,
+            mode,
+            moving,
+            items: Vec::new(),
+            ops: Vec::new(),
+        }
+    }
+
+    fn report(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        status: ItemStatus,
+        error: Option<String>,
+    ) {
+        self.items.push(TransferItem {
+            source: source.to_string_lossy().to_string(),
+            destination: destination.to_string_lossy().to_string(),
+            status,
+            error,
+        });
+    }
+
+    fn fail(&mut self, source: &Path, destination: &Path, error: impl ToString) {
+        self.report(
+            source,
+            destination,
+            ItemStatus::Failed,
+            Some(error.to_string()),
+        );
+    }
+
+    pub fn run(&mut self, source: &Path, destination: &Path) {
+        let metadata = match fs::symlink_metadata(source) {
+            Ok(metadata) => metadata,
+            Err(e) => return self.fail(source, destination, e),
+        };
+        self.progress.measure(source);
+        let source_real = source.canonicalize().ok();
+        let destination_real = resolve(destination);
+
+        if metadata.is_dir()
+            && source_real != destination_real
+            && destination_real
+                .as_ref()
+                .zip(source_real.as_ref())
+                .is_some_and(|(dest, src)| dest.starts_with(src))
+        {
+            return self.fail(
+                source,
+                destination,
+                "Can't copy or move a folder into itself",
+            );
+        }
+
+        if source_real.is_some() && source_real == destination_real {
+            match (self.moving, self.mode) {
+                (false, ConflictMode::KeepBoth) => {
+                    let renamed = free_name(destination, metadata.is_dir());
+                    self.place(source, &renamed, ItemStatus::Renamed);
+                }
+                (false, ConflictMode::Ask) => {
+                    self.report(source, destination, ItemStatus::Conflict, None)
+                }
+                (false, ConflictMode::Overwrite) => {
+                    self.fail(source, destination, "Can't overwrite an item with itself")
+                }
+                _ => self.report(source, destination, ItemStatus::Skipped, None),
+            }
+            return;
+        }
+
+        self.transfer(source, &metadata, destination);
+    }
+
+    fn transfer(&mut self, source: &Path, metadata: &Metadata, destination: &Path) {
+        let existing = match fs::symlink_metadata(destination) {
+            Ok(existing) => existing,
+            Err(_) => {
+                let status = if self.moving {
+                    ItemStatus::Moved
+                } else {
+                    ItemStatus::Copied
+                };
+                return self.place(source, destination, status);
+            }
+        };
+
+        match self.mode {
+            ConflictMode::Ask => self.report(source, destination, ItemStatus::Conflict, None),
+            ConflictMode::Skip => self.report(source, destination, ItemStatus::Skipped, None),
+            ConflictMode::KeepBoth => {
+                let renamed = free_name(destination, metadata.is_dir());
+                self.place(source, &renamed, ItemStatus::Renamed);
+            }
+            ConflictMode::Overwrite if metadata.is_dir() && existing.is_dir() => {
+                self.merge(source, destination)
+            }
+            ConflictMode::Overwrite => match self.history.stash(destination, self.progress) {
+                Ok(op) => {
+                    self.ops.push(op);
+                    self.place(source, destination, ItemStatus::Overwritten);
+                }
+                Err(e) => self.fail(source, destination, e),
+            },
+        }
+    }
+
+    /// Transfers the contents of one folder into an existing one.
+    fn merge(&mut self, source: &Path, destination: &Path) {
+        let entries = match fs::read_dir(source) {
+            Ok(entries) => entries,
+            Err(e) => return self.fail(source, destination, e),
+        };
+        for entry in entries {
+            if self.progress.is_cancelled() {
+                return;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.fail(source, destination, e);
+                    continue;
+                }
+            };
+            let from = entry.path();
+            match fs::symlink_metadata(&from) {
+                Ok(metadata) => {
+                    self.transfer(&from, &metadata, &destination.join(entry.file_name()))
+                }
+                Err(e) => self.fail(&from, &destination.join(entry.file_name()), e),
+            }
+        }
+
+        // A moved folder goes away once everything in it has been moved
+        if self.moving {
+            let _ = fs::remove_dir(source);
+        }
+    }
+
+    fn place(&mut self, source: &Path, destination: &Path, status: ItemStatus) {
+        if self.moving {
+            match move_path(source, destination, self.progress) {
+                Ok(_) => {
+                    self.ops.push(FileOp::Move {
+                        from: source.to_path_buf(),
+                        to: destination.to_path_buf(),
+                    });
+                    self.report(source, destination, status, None);
+                }
+                Err(_) if self.progress.is_cancelled() => {
+                    self.report(source, destination, ItemStatus::Cancelled, None)
+                }
+                Err(e) => self.fail(source, destination, e),
+            }
+            return;
+        }
+
+        let mut failures = Vec::new();
+        match copy_tree(source, destination, &mut failures, self.progress) {
+            Ok(_) => {
+                self.ops.push(FileOp::Copy {
+                    source: source.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                    trashed: self.history.trash_path(destination),
+                });
+                self.report(source, destination, status, None);
+                for (from, to, e) in failures {
+                    self.fail(&from, &to, e);
+                }
+            }
+            Err(_) if self.progress.is_cancelled() => {
+                // What was copied before the cancel is kept as the partial
+                // result and can be undone like a finished copy
+                if path_exists(destination) {
+                    self.ops.push(FileOp::Copy {
+                        source: source.to_path_buf(),
+                        destination: destination.to_path_buf(),
+                        trashed: self.history.trash_path(destination),
+                    });
+                }
+                self.report(source, destination, ItemStatus::Cancelled, None);
+            }
+            Err(e) => {
+                // Clean up whatever part of a new item was created
+                if path_exists(destination) {
+                    let _ = remove_path(destination);
+                }
+                self.fail(source, destination, e);
+            }
+        }
+    }
+
+    /// Records the transfer as one undoable operation and summarizes it.
+    pub fn finish(self) -> TransferResult {
+        self.history.record_all(self.ops);
+
+        let count = |status| {
+            self.items
+                .iter()
+                .filter(|item| item.status == status)
+                .count()
+        };
+        let done = count(ItemStatus::Copied)
+            + count(ItemStatus::Moved)
+            + count(ItemStatus::Overwritten)
+            + count(ItemStatus::Renamed);
+        let skipped = count(ItemStatus::Skipped);
+        let conflicts = count(ItemStatus::Conflict);
+        let failed = count(ItemStatus::Failed);
+
+        let cancelled = self.progress.is_cancelled();
+        let verb = if self.moving { "Moved" } else { "Copied" };
+        let mut message = format!("{} {} item(s)", verb, done);
+        if cancelled {
+            message = format!("Cancelled. {}", message);
+        } else {
+            self.progress.complete();
+        }
+        if skipped > 0 {
+            message.push_str(&format!(", skipped {}", skipped));
+        }
+        if conflicts > 0 {
+            message.push_str(&format!(", {} already exist", conflicts));
+        }
+        if failed > 0 {
+            let first = self
+                .items
+                .iter()
+                .find_map(|item| item.error.as_deref())
+                .unwrap_or_default();
+            message.push_str(&format!(", {} failed: {}", failed, first));
+        }
+
+        TransferResult {
+            success: !cancelled && conflicts == 0 && failed == 0,
+            message,
+            items: self.items,
+            cancelled,
+            progress: self.progress.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+        history: FileHistory,
+        progress: Progress,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let history = FileHistory::new(dir.path().join("trash"));
+            Fixture {
+                dir,
+                history,
+                progress: Progress::silent(),
+            }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.path().join(name)
+        }
+
+        fn file(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.path(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn run(
+            &self,
+            mode: ConflictMode,
+            moving: bool,
+            source: &Path,
+            destination: &Path,
+        ) -> TransferResult {
+            let mut transfer = Transfer::new(&self.history, &self.progress, mode, moving);
+            transfer.run(source, destination);
+            transfer.finish()
+        }
+    }
+
+    fn statuses(result: &TransferResult) -> Vec<ItemStatus> {
+        result.items.iter().map(|item| item.status).collect()
+    }
+
+    #[test]
+    fn skip_leaves_the_existing_file() {
+        let fixture = Fixture::new();
+        let source = fixture.file("a/file.txt", "new");
+        let destination = fixture.file("b/file.txt", "old");
+
+        let result = fixture.run(ConflictMode::Skip, false, &source, &destination);
+        assert_eq!(statuses(&result), vec![ItemStatus::Skipped]);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+    }
+
+    #[test]
+    fn ask_reports_the_conflict() {
+        let fixture = Fixture::new();
+        let source = fixture.file("a/file.txt", "new");
+        let destination = fixture.file("b/file.txt", "old");
+
+        let result = fixture.run(ConflictMode::Ask, true, &source, &destination);
+        assert!(!result.success);
+        assert_eq!(statuses(&result), vec![ItemStatus::Conflict]);
+        assert!(path_exists(&source));
+    }
+
+    #[test]
+    fn overwrite_replaces_files_and_merges_folders() {
+        let fixture = Fixture::new();
+        fixture.file("a/same.txt", "new");
+        fixture.file("a/only-new.txt", "new");
+        fixture.file("b/same.txt", "old");
+        fixture.file("b/only-old.txt", "old");
+
+        let result = fixture.run(
+            ConflictMode::Overwrite,
+            false,
+            &fixture.path("a"),
+            &fixture.path("b"),
+        );
+        let statuses = statuses(&result);
+        assert_eq!(statuses.len(), 2);
+        assert!(statuses.contains(&ItemStatus::Copied));
+        assert!(statuses.contains(&ItemStatus::Overwritten));
+        assert_eq!(
+            fs::read_to_string(fixture.path("b/same.txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_to_string(fixture.path("b/only-new.txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs::read_to_string(fixture.path("b/only-old.txt")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn keep_both_picks_the_next_free_name() {
+        let fixture = Fixture::new();
+        let source = fixture.file("a/file.txt", "new");
+        let destination = fixture.file("b/file.txt", "old");
+        fixture.file("b/file (1).txt", "older");
+
+        let result = fixture.run(ConflictMode::KeepBoth, false, &source, &destination);
+        assert_eq!(statuses(&result), vec![ItemStatus::Renamed]);
+        assert_eq!(
+            fs::read_to_string(fixture.path("b/file (2).txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "old");
+    }
+
+    #[test]
+    fn free_name_numbers_folders_without_splitting_the_extension() {
+        let fixture = Fixture::new();
+        fs::create_dir(fixture.path("archive.d")).unwrap();
+        assert_eq!(
+            free_name(&fixture.path("archive.d"), true),
+            fixture.path("archive.d (1)")
+        );
+        assert_eq!(
+            free_name(&fixture.path("notes.tar.gz"), false),
+            fixture.path("notes.tar (1).gz")
+        );
+    }
+
+    #[test]
+    fn copying_onto_itself_with_keep_both_makes_a_numbered_copy() {
+        let fixture = Fixture::new();
+        let source = fixture.file("file.txt", "text");
+
+        let result = fixture.run(ConflictMode::KeepBoth, false, &source, &source);
+        assert_eq!(statuses(&result), vec![ItemStatus::Renamed]);
+        assert_eq!(
+            fs::read_to_string(fixture.path("file (1).txt")).unwrap(),
+            "text"
+        );
+    }
+
+    #[test]
+    fn refuses_to_copy_a_folder_into_itself() {
+        let fixture = Fixture::new();
+        fixture.file("folder/file.txt", "text");
+        let folder = fixture.path("folder");
+
+        let result = fixture.run(ConflictMode::Ask, false, &folder, &folder.join("inner"));
+        assert_eq!(statuses(&result), vec![ItemStatus::Failed]);
+        assert_eq!(
+            result.items[0].error.as_deref(),
+            Some("Can't copy or move a folder into itself")
+        );
+        assert!(!path_exists(&folder.join("inner")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn move_on_the_same_device_renames_in_place() {
+        use std::os::unix::fs::MetadataExt;
+
+        let fixture = Fixture::new();
+        let source = fixture.file("a/file.txt", "text");
+        let destination = fixture.path("file.txt");
+        let inode = fs::metadata(&source).unwrap().ino();
+
+        move_path(&source, &destination, &fixture.progress).unwrap();
+        assert!(!path_exists(&source));
+        assert_eq!(fs::metadata(&destination).unwrap().ino(), inode);
+    }
+
+    #[test]
+    fn move_reports_failures_other_than_crossing_devices() {
+        let fixture = Fixture::new();
+        let source = fixture.file("a/file.txt", "text");
+
+        let error = move_path(
+            &source,
+            &fixture.path("missing/file.txt"),
+            &fixture.progress,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(path_exists(&source));
+    }
+}
@@ -6,7 +6,7 @@ import {
 } from "react-resizable-panels";
 import { ChatSidebar } from './components/ChatSidebar';
 import { useFileSystem } from './hooks/useFileSystem';
-import { open } from '@tauri-apps/plugin-dialog';
+import { invoke } from '@tauri-apps/api/core';
 import { WebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { Button } from './components/ui/button';
 import { useTheme } from './components/theme-provider';
@@ -17,7 +17,7 @@ import { FileTreeContainer } from './components/FileTreeContainer';
 import { useKeybindings } from './hooks/useKeybindings';
 import { useCommandRegistry } from './commands/registry';
 import { useContextKeys } from './commands/contextKeys';
-import { Toaster } from 'sonner';
+import { Toaster, toast } from 'sonner';
 import { ProjectInitDialog } from './components/ProjectInitDialog';
 import './App.css';
 
@@ -58,11 +58,10 @@ const App: React.FC = () => {
 
   const handleOpenFolder = async () => {
     try {
-      const selected = await open({
-        directory: true,
-        multiple: false,
-      });
-      if (selected && typeof selected === 'string') {
+      // The folder is picked on the Rust side so only the user can widen
+      // what the file commands may touch
+      const selected = await invoke<string | null>('open_workspace');
+      if (selected) {
         await loadFolder(selected);
       }
     } catch (err) {
@@ -108,6 +107,22 @@ const App: React.FC = () => {
   }, [registerCommand, unregisterCommand, workspacePath, createNewFile, createNewFolder,
     saveCurrentFile, saveFileAs, closeCurrentFile, isSidebarVisible, isChatVisible, handleOpenFolder, setContext]);
 
+  useEffect(() => {
+    // The database is opened before the page loads, so a failed open or
+    // migration is fetched here rather than sent as an event
+    const checkDatabase = async () => {
+      try {
+        const error = await invoke<string | null>('get_database_status');
+        if (error) {
+          toast.error(`Chat history is unavailable: ${error}`, { duration: Infinity });
+        }
+      } catch (err) {
+        console.error('Failed to check database status:', err);
+      }
+    };
+    checkDatabase();
+  }, []);
+
   useEffect(() => {
     const checkDetachedWindow = async () => {
       const chatWindow = await WebviewWindow.getByLabel("chat");
@@ -1,5 +1,7 @@
-import React, { useState } from 'react';
-import { readDir, readTextFile } from '@tauri-apps/plugin-fs';
+import React, { useEffect, useRef, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { v4 as uuidv4 } from 'uuid';
 
 interface SearchResult {
     file: string;
@@ -7,40 +9,49 @@ interface SearchResult {
     preview: string;
 }
 
-async function searchFiles(dir: string, query: string, results: SearchResult[], maxDepth = 10) {
-    if (maxDepth < 0) return;
-    try {
-        const entries = await readDir(dir);
-        // for (const entry of entries) {
-        //     if (entry.name) {
-        //         await searchFiles(entry.path, query, results, maxDepth - 1);
-        //     } else {
-        //         try {
-        //             const content = await readTextFile(entry.path);
-        //             const lines = content.split('\n');
-        //             lines.forEach((line, idx) => {
-        //                 if (line.toLowerCase().includes(query.toLowerCase())) {
-        //                     results.push({ file: entry.path, line: idx + 1, preview: line.trim() });
-        //                 }
-        //             });
-        //         } catch { }
-        //     }
-        // }
-    } catch { }
+interface WorkspaceSearchResult {
+    search_id: string;
+    path: string;
+    matches: Array<{ line: number; column: number; preview: string }>;
 }
 
 const GlobalSearch: React.FC<{ root: string }> = ({ root }) => {
     const [query, setQuery] = useState('');
     const [results, setResults] = useState<SearchResult[]>([]);
     const [loading, setLoading] = useState(false);
+    const searchId = useRef<string | null>(null);
+
+    useEffect(() => {
+        const unlisten = listen<WorkspaceSearchResult>('workspace_search_result', (event) => {
+            if (event.payload.search_id !== searchId.current) return;
+            const found = event.payload.matches.map(m => ({
+                file: event.payload.path,
+                line: m.line,
+                preview: m.preview.trim(),
+            }));
+            setResults(prev => [...prev, ...found]);
+        });
+        return () => {
+            unlisten.then(fn => fn());
+        };
+    }, []);
 
     const handleSearch = async (e: React.FormEvent) => {
         e.preventDefault();
+        if (searchId.current) {
+            invoke('cancel_workspace_search', { searchId: searchId.current }).catch(() => { });
+        }
+        const id = uuidv4();
+        searchId.current = id;
+        setResults([]);
         setLoading(true);
-        const found: SearchResult[] = [];
-        await searchFiles(root, query, found);
-        setResults(found);
-        setLoading(false);
+        try {
+            await invoke('search_workspace', { searchId: id, root, query });
+        } catch (error) {
+            console.error('Workspace search failed:', error);
+        } finally {
+            if (searchId.current === id) setLoading(false);
+        }
     };
 
     return (
@@ -10,6 +10,15 @@ interface FileMetadata {
   is_dir: boolean;
   size: number;
   modified: number;
+  created?: number;
+  is_symlink: boolean;
+  symlink_target?: string;
+  readonly: boolean;
+  mode?: number;
+  hidden: boolean;
+  mime_type?: string;
+  language?: string;
+  git_status?: 'conflicted' | 'untracked' | 'added' | 'deleted' | 'renamed' | 'modified' | 'ignored';
   children?: FileMetadata[];
 }
 
@@ -126,6 +126,11 @@ const useChatStoreImpl = create<ChatState>((set, get) => ({
       const fullPath = await join(localDataDir, 'models', modelName);
 
       await llmService.loadModel(fullPath);
+      // Token counting for the context budget uses the model's tokenizer
+      // when one is found beside it, and estimates otherwise
+      await invoke('set_context_model', { modelPath: fullPath }).catch((error) =>
+        console.error('Failed to set context model:', error)
+      );
 
       set({ context: true, isLoading: false });
       console.log('Model loaded successfully');
@@ -138,7 +143,7 @@ const useChatStoreImpl = create<ChatState>((set, get) => ({
   },
 
   handleSendMessage: async () => {
-    const { context, userInput, conversation, addMessage, setUserInput, setIsLoading, setIsGenerating, updateLastMessage, currentSessionId } = get();
+    const { context, userInput, addMessage, setUserInput, setIsLoading, setIsGenerating, updateLastMessage, currentSessionId, selectedModel } = get();
 
     if (!context) {
       alert("Please load the model first.");
@@ -153,8 +158,6 @@ const useChatStoreImpl = create<ChatState>((set, get) => ({
     setIsLoading(true);
     setIsGenerating(true);
 
-    const newConversation = [...conversation, userMessage];
-
     try {
       // Save user message to database and get a unique ID for the exchange
       const messageId = uuidv4();
@@ -165,8 +168,18 @@ const useChatStoreImpl = create<ChatState>((set, get) => ({
         response: ""
       });
 
+      // The prompt is built from the stored branch so it fits the model's
+      // context, with older turns summarized once they no longer fit
+      const assembled = await invoke<{ messages: Message[] }>('build_context', {
+        sessionId: currentSessionId,
+      });
+      const messages = assembled.messages.some((message) => message.role === 'system')
+        ? assembled.messages
+        : [...INITIAL_CONVERSATION, ...assembled.messages];
+
+      const startedAt = performance.now();
       await llmService.generate(
-        newConversation,
+        messages,
         async (token) => {
           updateLastMessage(token);
         },
@@ -177,7 +190,9 @@ const useChatStoreImpl = create<ChatState>((set, get) => ({
           const lastMessage = get().conversation[get().conversation.length - 1];
           await invoke('update_message_response', {
             id: messageId,
-            response: lastMessage.content
+            response: lastMessage.content,
+            model: selectedModel?.name ?? null,
+            latencyMs: Math.round(performance.now() - startedAt)
           });
         }
       );